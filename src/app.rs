@@ -0,0 +1,1022 @@
+//! The command line application: syncing, rebuilding and importing the archive.
+
+use crate::child_info::{self, ChildInfo};
+use crate::cli::{Args, Command};
+use crate::client::FamlyClient;
+use crate::config::{ChildSelection, Config, NotifyConfig, StorageConfig, UploadConfig};
+use crate::date_range::DateRange;
+use crate::digest::{DigestPost, Envelope};
+use crate::file_system::{create_alias, create_dir};
+use crate::immich::{ExportedPhoto, ImmichClient};
+use crate::notify::{NewPost, Notifier};
+use crate::page::{Identified, Page};
+use crate::post::{Post, PostSummary, Photo};
+use crate::s3::S3Storage;
+use crate::smtp::SmtpServer;
+use crate::source::FeedSource;
+use crate::storage::{LocalStorage, Storage};
+use crate::webdav::WebDavFolder;
+use crate::schedule::Schedule;
+use crate::serve::SearchablePost;
+use crate::{config, console, daemon, digest, export, har, html, http, immich, notify, page, post, raw, search_index, serve, shutdown, sidecar, sqlite, storage, token, upload};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use error_chain::error_chain;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+error_chain! {
+    links {
+        ChildInfo(child_info::Error, child_info::ErrorKind);
+        Config(config::Error, config::ErrorKind);
+        Token(token::Error, token::ErrorKind);
+        Post(post::Error, post::ErrorKind);
+        Http(http::Error, http::ErrorKind);
+        Har(har::Error, har::ErrorKind);
+        Storage(storage::Error, storage::ErrorKind);
+        Upload(upload::Error, upload::ErrorKind);
+        Immich(immich::Error, immich::ErrorKind);
+        Notify(notify::Error, notify::ErrorKind);
+        Digest(digest::Error, digest::ErrorKind);
+        Daemon(daemon::Error, daemon::ErrorKind);
+        Serve(serve::Error, serve::ErrorKind);
+        Sqlite(sqlite::Error, sqlite::ErrorKind);
+        Export(export::Error, export::ErrorKind);
+    }
+    foreign_links {
+        Io(std::io::Error);
+    }
+    errors {
+        Interrupted {
+            description("interrupted")
+            display("Interrupted by a shutdown request")
+        }
+    }
+}
+
+/// Picks the children configured in advance or, if none are configured, lets the user pick
+/// one child or all of them at once.
+fn choose_target_children<'a>(child_infos: &'a [ChildInfo], selection: &ChildSelection) -> Result<Vec<&'a ChildInfo>> {
+    if !selection.is_empty() {
+        let children = child_info::select(child_infos, selection)?;
+        for child in &children {
+            println!("{0} is selected ({1})", child.get_first_name(), child.id);
+        }
+        return Ok(children);
+    }
+
+    let children_count = child_infos.len();
+
+    if children_count < 2 {
+        let child = &child_infos[0];
+        return Ok(vec![child]);
+    }
+
+    if !console::is_interactive() {
+        return Err(Error::from(
+            "Several children found, but input is not interactive: select them with --child, --institution or --all-children"));
+    }
+
+    loop {
+        if let Some(child_number) = console::choose_number(
+            "Select the child (0 for all children, CTRL+C to exit): ", 0, children_count)? {
+            if child_number == 0 {
+                println!("All children are selected");
+                return Ok(child_infos.iter().collect());
+            }
+
+            let child = &child_infos[child_number - 1];
+            println!("{0} is selected ({1})", child.get_first_name(), child.id);
+            return Ok(vec![child]);
+        }
+
+        println!("Invalid number")
+    }
+}
+
+/// Stores posts to the child's archive and downloads related photos, unless working offline without a source.
+fn store_posts(storage: &dyn Storage, source: Option<&dyn FeedSource>, posts: &[&Post], child: &ChildInfo, folder: &str)
+    -> Result<()> {
+    for &p in posts {
+        // Create HTM file with post content.
+        let html = html::render_post(p, child);
+        storage.put(&format!("{0}/posts/{1}", folder, p.get_file_name()), html.as_bytes())?;
+
+        // Download photos and link the tagged ones.
+        for ph in &p.photos {
+            let photo_file_name = ph.get_file_name();
+
+            let photo_path = format!("{0}/posts/photos/{1}", folder, photo_file_name);
+            if !storage.exists(&photo_path)? {
+                let source = match source {
+                    Some(source) => source,
+                    None => {
+                        println!("{0} is missing, it cannot be downloaded offline", photo_path);
+                        continue;
+                    },
+                };
+                storage.put(&photo_path, &download_image(source, &ph.url)?)?;
+            }
+
+            if ph.is_tagged(&child.id) {
+                let tagged_photo_path = format!("{0}/tagged_photos/{1}", folder, photo_file_name);
+                if !storage.exists(&tagged_photo_path)? {
+                    storage.link(&photo_path, &tagged_photo_path)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn download_tagged_photos(storage: &dyn Storage, source: &dyn FeedSource, photos: &[Photo], folder: &str) -> Result<()> {
+    for p in photos {
+        let photo_path = format!("{0}/tagged_photos/{1}", folder, p.get_file_name());
+        if !storage.exists(&photo_path)? {
+            storage.put(&photo_path, &download_image(source, &p.url)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn download_image(source: &dyn FeedSource, url: &str) -> Result<Vec<u8>> {
+    let mut image = vec![];
+    source.download_image(url, &mut image)?;
+    Ok(image)
+}
+
+/// Stores every page of posts as soon as it arrives. The posts are shared by all the children.
+/// Posts not stored before are added to `new_posts`.
+fn sync_posts<I, E>(storage: &dyn Storage, source: &dyn FeedSource, pages: I, children: &[&ChildInfo], env: &Config,
+    new_posts: &mut Vec<NewPost>) -> Result<()>
+    where
+        I: Iterator<Item = std::result::Result<Vec<Post>, E>>,
+        Error: From<E>,
+{
+    let mut count = 0;
+    for page in pages {
+        check_shutdown()?;
+        let mut posts = page?;
+        posts.retain(|p| env.date_range.contains(&p.date));
+        if posts.is_empty() {
+            continue;
+        }
+
+        for post in &posts {
+            if let Some(child) = children.iter().find(|c| post.is_tagged(&c.id)) {
+                if !storage.exists(&format!("{0}/posts/{1}", child.get_folder_name(), post.get_file_name()))? {
+                    new_posts.push(NewPost::new(post));
+                }
+            }
+        }
+
+        for child in children {
+            let child_posts: Vec<&Post> = posts.iter().filter(|p| p.is_tagged(&child.id)).collect();
+            store_posts(storage, Some(source), &child_posts, child, &child.get_folder_name())?;
+        }
+
+        count += posts.len();
+        println!("{} matching posts stored...", count);
+    }
+
+    println!("All {0} matching posts stored", count);
+    Ok(())
+}
+
+/// Downloads the child's tagged photos page by page. Returns the number of photos.
+fn sync_tagged_photos<I, E>(storage: &dyn Storage, source: &dyn FeedSource, pages: I, folder: &str, date_range: &DateRange)
+    -> Result<usize>
+    where
+        I: Iterator<Item = std::result::Result<Vec<Photo>, E>>,
+        Error: From<E>,
+{
+    let mut tagged_photos_count = 0;
+    for page in pages {
+        check_shutdown()?;
+        let mut photos = page?;
+        photos.retain(|p| date_range.contains(&p.date));
+        if photos.is_empty() {
+            continue;
+        }
+
+        download_tagged_photos(storage, source, &photos, folder)?;
+        tagged_photos_count += photos.len();
+        println!("{} tagged photos downloaded...", tagged_photos_count);
+    }
+
+    println!("All {0} tagged photos downloaded", tagged_photos_count);
+    Ok(tagged_photos_count)
+}
+
+/// Fetches posts once for all the children and stores them.
+fn fetch_posts<S: FeedSource>(storage: &dyn Storage, source: &S, children: &[&ChildInfo], env: &Config,
+    new_posts: &mut Vec<NewPost>) -> Result<()> {
+    println!("Fetching posts...");
+
+    let child_ids: Vec<&String> = children.iter().map(|c| &c.id).collect();
+    let mut recorder = raw::Recorder::new(&env.output_dir.join("raw").join("feed"));
+    let pages = source.posts_with(&child_ids, &env.date_range, |json| Ok(recorder.save(json)?));
+
+    sync_posts(storage, source, pages, children, env, new_posts)
+}
+
+/// Fetches the child's tagged photos and downloads them.
+fn fetch_tagged_photos<S: FeedSource>(storage: &dyn Storage, source: &S, child: &ChildInfo, env: &Config) -> Result<usize> {
+    println!("Fetching tagged photos...");
+
+    let folder = child.get_folder_name();
+    let mut recorder = raw::Recorder::new(&env.output_dir.join(&folder).join("raw").join("tagged_photos"));
+    let pages = source.tagged_photos_with(&child.id, &env.date_range, |json| Ok(recorder.save(json)?));
+
+    sync_tagged_photos(storage, source, pages, &folder, &env.date_range)
+}
+
+/// Imports posts and tagged photos from API responses saved in a HAR file, photos are still downloaded.
+/// The responses are recorded like the ones of a regular run, so `rebuild` covers them too.
+fn import_har(env: &Config, path: &Path) -> Result<()> {
+    let har = har::read(path)?;
+    let images = FamlyClient::anonymous(&env.api_url);
+    let storage = open_storage(env)?;
+    let storage = storage.as_ref();
+    let child_infos_json = har.child_infos
+        .ok_or("The HAR file has no list of children: reload the Famly page with DevTools open before saving it")?;
+
+    raw::save(&create_raw_dir(&env.output_dir)?.join("children.json.gz"), &child_infos_json)?;
+    let child_infos = child_info::from_json(child_infos_json)?;
+    let children = pick_children(&child_infos, env)?;
+    prepare_folders(&children, &child_infos, env)?;
+
+    let mut new_posts = vec![];
+    if env.include.posts {
+        println!("Importing {0} pages of posts...", har.feed_pages.len());
+        let child_ids: Vec<&String> = children.iter().map(|c| &c.id).collect();
+        let mut recorder = raw::Recorder::new(&env.output_dir.join("raw").join("feed"));
+        let mut seen_ids = HashSet::new();
+        let pages = har.feed_pages.into_iter().map(|json| -> Result<Vec<Post>> {
+            recorder.save(&json)?;
+            let page = Post::from_feed_json(json, &child_ids)?;
+            Ok(page::dedupe(page.items, &mut seen_ids))
+        });
+        sync_posts(storage, &images, pages, &children, env, &mut new_posts)?;
+    }
+    let mut recorded = RecordedPhotos::load_posts(&children, env)?;
+    let posts = summarize_stored_posts(storage, &children, &recorded)?;
+
+    let mut tagged_photos_pages = har.tagged_photos_pages;
+    for child in &children {
+        println!("\nImporting {0} ({1})...", child.get_first_name(), child.id);
+        let folder = child.get_folder_name();
+
+        let mut tagged_photos_count = 0;
+        if env.include.tagged_photos {
+            let jsons = tagged_photos_pages.remove(&child.id).unwrap_or_default();
+            println!("Importing {0} pages of tagged photos...", jsons.len());
+            let mut recorder = raw::Recorder::new(&env.output_dir.join(&folder).join("raw").join("tagged_photos"));
+            let mut seen_ids = HashSet::new();
+            let pages = jsons.into_iter().map(|json| -> Result<Vec<Photo>> {
+                recorder.save(&json)?;
+                let page = Photo::from_json_array(json)?;
+                Ok(page::dedupe(page.items, &mut seen_ids))
+            });
+            tagged_photos_count = sync_tagged_photos(storage, &images, pages, &folder, &env.date_range)?;
+        }
+        recorded.load_tagged_photos(child, env)?;
+
+        let has_tagged_photos = tagged_photos_count > 0 || has_stored_tagged_photos(storage, &folder)?;
+        write_index(storage, &posts, child, has_tagged_photos, &folder)?;
+    }
+
+    write_family_index(storage, &posts, &children)?;
+    write_derived_files(storage, &children, &child_infos, &recorded, env)?;
+    export_archive(&children, &child_infos, &recorded, env)?;
+    notify_new_posts(&new_posts, env);
+    recorded.prune_redundant_runs()
+}
+
+/// Creates the child's `index.htm` listing the posts the child is tagged in.
+fn write_index(storage: &dyn Storage, posts: &[PostSummary], child: &ChildInfo, has_tagged_photos: bool, folder: &str)
+    -> Result<()> {
+    let posts: Vec<&PostSummary> = posts.iter().filter(|p| p.is_tagged(&child.id)).collect();
+    println!("{0} posts in the index", posts.len());
+
+    if !posts.is_empty() || has_tagged_photos {
+        let html = html::render_index(&posts, has_tagged_photos);
+        storage.put(&format!("{0}/index.htm", folder), html.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Creates the combined family `index.htm`.
+fn write_family_index(storage: &dyn Storage, posts: &[PostSummary], children: &[&ChildInfo]) -> Result<()> {
+    if children.len() > 1 {
+        let posts: Vec<&PostSummary> = posts.iter().collect();
+        let html = html::render_family_index(&posts, children);
+        storage.put("index.htm", html.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Returns summaries of the recorded posts stored in the archive of at least one of the children, newest first.
+/// The indexes list all of them, a run limited to a date range must not drop the older or newer posts.
+fn summarize_stored_posts(storage: &dyn Storage, children: &[&ChildInfo], recorded: &RecordedPhotos)
+    -> Result<Vec<PostSummary>> {
+    let mut stored = HashSet::new();
+    for child in children {
+        stored.extend(storage.list(&format!("{0}/posts", child.get_folder_name()))?);
+    }
+
+    let mut summaries: Vec<PostSummary> = recorded.posts.iter()
+        .filter(|p| children.iter()
+            .any(|c| p.is_tagged(&c.id)
+                && stored.contains(&format!("{0}/posts/{1}", c.get_folder_name(), p.get_file_name()))))
+        .map(Post::summarize)
+        .collect();
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.date));
+    Ok(summaries)
+}
+
+/// Returns true if the archive of the child has tagged photos, downloaded by this or an earlier run.
+fn has_stored_tagged_photos(storage: &dyn Storage, folder: &str) -> Result<bool> {
+    Ok(!storage.list(&format!("{0}/tagged_photos", folder))?.is_empty())
+}
+
+/// Writes the files derived from the posts and photos recorded by this and previous runs:
+/// search indexes, sidecars and the exports. They cover the whole archive, whatever the date range of the run.
+fn write_derived_files(storage: &dyn Storage, children: &[&ChildInfo], child_infos: &[ChildInfo],
+    recorded: &RecordedPhotos, env: &Config) -> Result<()> {
+    write_search_indexes(storage, children, recorded, env)?;
+    write_sidecars(storage, children, child_infos, recorded, env)?;
+    export_to_sqlite(children, child_infos, recorded, env)?;
+    export_to_files(children, recorded, env)
+}
+
+/// Writes the search indexes of the index pages.
+fn write_search_indexes(storage: &dyn Storage, children: &[&ChildInfo], recorded: &RecordedPhotos, env: &Config)
+    -> Result<()> {
+    if !env.include.posts {
+        return Ok(());
+    }
+
+    let mut recorded: Vec<&Post> = recorded.posts.iter().collect();
+    recorded.sort_by_key(|p| std::cmp::Reverse(p.date));
+    for child in children {
+        let posts: Vec<(&Post, String)> = recorded.iter()
+            .filter(|p| p.is_tagged(&child.id))
+            .map(|p| (*p, format!("posts/{0}", p.get_file_name())))
+            .collect();
+        let index = search_index::render(&posts);
+        storage.put(&format!("{0}/{1}", child.get_folder_name(), search_index::FILE_NAME), index.as_bytes())?;
+    }
+
+    if children.len() > 1 {
+        let posts: Vec<(&Post, String)> = recorded.iter()
+            .filter_map(|p| {
+                let child = children.iter().find(|c| p.is_tagged(&c.id))?;
+                Some((*p, format!("{0}/posts/{1}", child.get_folder_name(), p.get_file_name())))
+            })
+            .collect();
+        storage.put(search_index::FILE_NAME, search_index::render(&posts).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Regenerates the archive purely from the recorded API responses, without network access.
+fn rebuild(env: &Config) -> Result<()> {
+    let raw_dir = env.output_dir.join("raw");
+    let child_infos_json = raw::load(&raw_dir.join("children.json.gz"))
+        .chain_err(|| format!("No recorded responses found in {0}, run a sync first", raw_dir.display()))?;
+    let child_infos = child_info::from_json(child_infos_json)?;
+    let children = pick_children(&child_infos, env)?;
+    let storage = open_storage(env)?;
+    let storage = storage.as_ref();
+
+    let mut recorded = RecordedPhotos::load_posts(&children, env)?;
+    if env.include.posts {
+        rebuild_posts(storage, &children, &recorded, env)?;
+    }
+    let posts = summarize_stored_posts(storage, &children, &recorded)?;
+
+    for child in &children {
+        println!("\nRebuilding {0} ({1})...", child.get_first_name(), child.id);
+        let folder = child.get_folder_name();
+
+        recorded.load_tagged_photos(child, env)?;
+        if env.include.tagged_photos {
+            count_recorded_tagged_photos(storage, &folder, &recorded.tagged_photos[&child.id], env)?;
+        }
+        let has_tagged_photos = has_stored_tagged_photos(storage, &folder)?;
+        write_index(storage, &posts, child, has_tagged_photos, &folder)?;
+    }
+
+    write_family_index(storage, &posts, &children)?;
+    write_derived_files(storage, &children, &child_infos, &recorded, env)?;
+    export_archive(&children, &child_infos, &recorded, env)?;
+    recorded.prune_redundant_runs()
+}
+
+/// Serves the archive of all recorded children, the search covers the recorded posts.
+fn serve(env: &Config, address: &str) -> Result<()> {
+    if !matches!(env.storage, StorageConfig::Local) {
+        return Err("Only an archive written to the output folder can be served".into());
+    }
+    let raw_dir = env.output_dir.join("raw");
+    let child_infos_json = raw::load(&raw_dir.join("children.json.gz"))
+        .chain_err(|| format!("No recorded responses found in {0}, run a sync first", raw_dir.display()))?;
+    let child_infos = child_info::from_json(child_infos_json)?;
+    let children: Vec<&ChildInfo> = child_infos.iter().collect();
+
+    serve::run(&env.output_dir, address, || {
+        let recorded = RecordedPhotos::load_posts(&children, env).map_err(|e| e.to_string())?;
+        let mut posts: Vec<SearchablePost> = recorded.posts.into_iter()
+            .filter_map(|post| {
+                let folder = children.iter().find(|c| post.is_tagged(&c.id))?.get_folder_name();
+                Some(SearchablePost { post, folder })
+            })
+            .collect();
+        posts.sort_by_key(|p| std::cmp::Reverse(p.post.date));
+        Ok(posts)
+    })?;
+    Ok(())
+}
+
+/// Emails the recorded posts that are new since the last digest, with the stored photos of the children.
+fn digest(env: &Config) -> Result<()> {
+    let digest_config = env.digest.as_ref().ok_or("No digest is configured: add a `digest` section to the profile")?;
+    let raw_dir = env.output_dir.join("raw");
+    let child_infos_json = raw::load(&raw_dir.join("children.json.gz"))
+        .chain_err(|| format!("No recorded responses found in {0}, run a sync first", raw_dir.display()))?;
+    let child_infos = child_info::from_json(child_infos_json)?;
+    let children = pick_children(&child_infos, env)?;
+
+    let recorded = RecordedPhotos::load_posts(&children, env)?;
+    let posts = recorded.posts.into_iter()
+        .filter(|post| env.date_range.contains(&post.date))
+        .filter_map(|post| {
+            let child = children.iter().find(|c| post.is_tagged(&c.id))?;
+            let photos_dir = env.output_dir.join(child.get_folder_name()).join("posts").join("photos");
+            let photos = post.photos.iter()
+                .filter(|p| children.iter().any(|c| p.is_tagged(&c.id)))
+                .map(|p| photos_dir.join(p.get_file_name()))
+                .collect();
+            Some(DigestPost { post, photos })
+        })
+        .collect();
+
+    let password = digest_config.password.as_ref().map(|p| p.read()).transpose()
+        .chain_err(|| "Cannot read the configured SMTP password")?;
+    let envelope = Envelope {
+        server: SmtpServer {
+            host: digest_config.smtp_host.clone(),
+            port: digest_config.smtp_port,
+            security: digest_config.security,
+            credentials: digest_config.username.clone().zip(password),
+        },
+        from: digest_config.from.clone(),
+        to: digest_config.to.clone(),
+    };
+    // Without a previous digest, the posts of the last day are new.
+    let first_since = env.date_range.since.unwrap_or_else(|| Utc::now() - Duration::days(1));
+
+    match digest::send(&env.output_dir, posts, first_since, &envelope)? {
+        0 => println!("No new posts since the last digest"),
+        sent => println!("The digest of {0} posts is sent to {1}", sent, envelope.to.join(", ")),
+    }
+    Ok(())
+}
+
+/// Stores the recorded posts within the date range.
+fn rebuild_posts(storage: &dyn Storage, children: &[&ChildInfo], recorded: &RecordedPhotos, env: &Config)
+    -> Result<()> {
+    println!("Rebuilding posts...");
+
+    let mut count = 0;
+    for post in recorded.posts.iter().filter(|p| env.date_range.contains(&p.date)) {
+        for child in children {
+            if post.is_tagged(&child.id) {
+                store_posts(storage, None, &[post], child, &child.get_folder_name())?;
+            }
+        }
+        count += 1;
+    }
+
+    println!("All {0} matching posts stored", count);
+    Ok(())
+}
+
+/// Returns the number of recorded tagged photos of the child that are present in the archive.
+fn count_recorded_tagged_photos(storage: &dyn Storage, folder: &str, photos: &[Photo], env: &Config) -> Result<usize> {
+    let stored: HashSet<String> = storage.list(&format!("{0}/tagged_photos", folder))?.into_iter().collect();
+    let mut count = 0;
+    for photo in photos.iter().filter(|p| env.date_range.contains(&p.date)) {
+        if stored.contains(&format!("{0}/tagged_photos/{1}", folder, photo.get_file_name())) {
+            count += 1;
+        } else {
+            println!("Tagged photo {0} is missing, it cannot be downloaded offline", photo.get_file_name());
+        }
+    }
+
+    println!("{0} tagged photos found", count);
+    Ok(count)
+}
+
+/// Lists the children found and picks the target ones.
+fn pick_children<'a>(child_infos: &'a [ChildInfo], env: &Config) -> Result<Vec<&'a ChildInfo>> {
+    if child_infos.is_empty() {
+        return Err(Error::from("No children found"));
+    }
+    if child_infos.len() > 1 && env.child_selection.is_empty() {
+        println!("\nFound children:");
+        println!("0. All children");
+        for (pos, ci) in child_infos.iter().enumerate() {
+            println!("{}. {} ({})", pos + 1, ci.full_name_with_institution, ci.institution);
+        }
+    }
+    println!();
+
+    choose_target_children(child_infos, &env.child_selection)
+}
+
+/// Creates the folder of recorded API responses shared by all children.
+fn create_raw_dir(output_dir: &Path) -> Result<std::path::PathBuf> {
+    let raw_dir = output_dir.join("raw");
+    std::fs::create_dir_all(&raw_dir)
+        .map_err(|e| format!("Cannot create the target folder: {0}", e))?;
+    Ok(raw_dir)
+}
+
+/// Reads the token and the passphrase from the user and writes the encrypted token cache.
+fn store_token(path: &Path) -> Result<()> {
+    let token = if console::is_interactive() {
+        rpassword::prompt_password("Access token: ")?
+    } else {
+        let mut token = String::new();
+        std::io::stdin().read_line(&mut token)?;
+        token
+    };
+    if token.trim().is_empty() {
+        return Err(Error::from("The access token is empty"));
+    }
+
+    let passphrase = token::read_passphrase("New passphrase: ")?;
+    if std::env::var("FAMLY_TOKEN_PASSPHRASE").is_err()
+        && passphrase != token::read_passphrase("Repeat the passphrase: ")? {
+        return Err(Error::from("Passphrases don't match"));
+    }
+
+    token::store_cache(path, &token, &passphrase)?;
+    println!("The token is stored to {0}", path.display());
+    Ok(())
+}
+
+/// Checks the access token by loading the list of children. If the token is missing or rejected
+/// and the login is configured, offers to log in to get a new one.
+fn authenticate(env: &Config) -> Result<(FamlyClient, String)> {
+    if let Some(token) = env.read_access_token()? {
+        let client = FamlyClient::new(&token, &env.api_url)?;
+        match client.child_infos_json() {
+            Ok(json) => return Ok((client, json)),
+            Err(http::Error(http::ErrorKind::Unauthorized(status), _)) if env.login.is_some() => {
+                println!("Famly rejected the access token (HTTP {0}), it has most likely expired", status);
+                if console::is_interactive()
+                    && !console::confirm("Log in with the configured credentials to get a new token? [Y/n] ")? {
+                    return Err(http::Error::from(http::ErrorKind::Unauthorized(status)).into());
+                }
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let login = env.login.as_ref().ok_or("No access token or login configured")?;
+    println!("Logging in as {0}...", login.email);
+    let password = login.password.read()
+        .chain_err(|| "Cannot read the configured password")?;
+    let client = FamlyClient::log_in(&env.api_url, &login.email, &password)?;
+    println!("Logged in. The new token is used for this run only, update the configured token to reuse it");
+
+    let json = client.child_infos_json()?;
+    Ok((client, json))
+}
+
+/// Runs the command given on the command line, syncing the archive by default.
+pub fn run(args: &Args) -> Result<()> {
+    if let Some(Command::StoreToken { file }) = &args.command {
+        return store_token(file);
+    }
+
+    let env = Config::new(args)?;
+    match &args.command {
+        Some(Command::Daemon { interval, cron, status_address }) =>
+            return run_daemon(&env, interval.as_deref(), cron.as_deref(), status_address.as_deref()),
+        // Only reads the archive, so it doesn't need the lock.
+        Some(Command::Serve { address }) => return serve(&env, address),
+        _ => {},
+    }
+
+    let _lock = lock_output_dir(&env)?;
+    match &args.command {
+        Some(Command::Rebuild) => return rebuild(&env),
+        Some(Command::ImportHar { file }) => return import_har(&env, file),
+        Some(Command::Digest) => return digest(&env),
+        _ => {},
+    }
+
+    let (client, child_infos_json) = authenticate(&env)?;
+    sync(&client, child_infos_json, &env)
+}
+
+/// Name of the file in the output folder locked while the archive is being written.
+const LOCK_FILE_NAME: &str = ".famly-dl.lock";
+
+/// Makes sure no other run writes the archive at the same time. The lock is released when the file is dropped,
+/// also if the process is killed.
+fn lock_output_dir(env: &Config) -> Result<std::fs::File> {
+    std::fs::create_dir_all(&env.output_dir)
+        .map_err(|e| format!("Cannot create the target folder: {0}", e))?;
+    let path = env.output_dir.join(LOCK_FILE_NAME);
+    let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => Err(format!(
+            "Another run of famly-dl is using {0}, try again when it's finished", env.output_dir.display()).into()),
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+fn check_shutdown() -> Result<()> {
+    if shutdown::is_requested() {
+        return Err(ErrorKind::Interrupted.into());
+    }
+    Ok(())
+}
+
+/// Syncs on the schedule from the command line or the configuration until SIGTERM or Ctrl+C,
+/// which stop a sync in progress between two pages or children.
+fn run_daemon(env: &Config, interval: Option<&str>, cron: Option<&str>, status_address: Option<&str>) -> Result<()> {
+    let schedule = match (interval, cron) {
+        (Some(interval), _) => Schedule::parse_interval(interval)?,
+        (_, Some(cron)) => Schedule::parse_cron(cron)?,
+        _ => match (&env.daemon.interval, &env.daemon.cron) {
+            (Some(interval), _) => Schedule::parse_interval(interval)?,
+            (_, Some(cron)) => Schedule::parse_cron(cron)?,
+            _ => return Err("No schedule: use --interval or --cron or configure the `daemon` of the profile".into()),
+        },
+    };
+    if env.child_selection.is_empty() {
+        return Err("The daemon can't ask which children to sync: use --child or --all-children or configure them".into());
+    }
+    let status_address = status_address.or(env.daemon.status_address.as_deref()).unwrap_or("127.0.0.1:8787");
+
+    shutdown::install_handlers();
+    daemon::run(&schedule, status_address, || {
+        let result = lock_output_dir(env).and_then(|_lock| {
+            let (client, child_infos_json) = authenticate(env)?;
+            sync(&client, child_infos_json, env)
+        });
+        result.map_err(|e| e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": "))
+    })?;
+    Ok(())
+}
+
+/// Syncs the archive of the children picked from the list with posts and photos of the source.
+fn sync<S: FeedSource>(source: &S, child_infos_json: String, env: &Config) -> Result<()> {
+    raw::save(&create_raw_dir(&env.output_dir)?.join("children.json.gz"), &child_infos_json)?;
+    let child_infos = child_info::from_json(child_infos_json)?;
+    let children = pick_children(&child_infos, env)?;
+
+    prepare_folders(&children, &child_infos, env)?;
+    let storage = open_storage(env)?;
+    let storage = storage.as_ref();
+
+    let mut new_posts = vec![];
+    if env.include.posts {
+        fetch_posts(storage, source, &children, env, &mut new_posts)?;
+    }
+    let mut recorded = RecordedPhotos::load_posts(&children, env)?;
+    let posts = summarize_stored_posts(storage, &children, &recorded)?;
+
+    for child in &children {
+        check_shutdown()?;
+        println!("\nSyncing {0} ({1})...", child.get_first_name(), child.id);
+
+        let folder = child.get_folder_name();
+        let tagged_photos_count = if env.include.tagged_photos {
+            fetch_tagged_photos(storage, source, child, env)?
+        } else {
+            0
+        };
+        recorded.load_tagged_photos(child, env)?;
+
+        let has_tagged_photos = tagged_photos_count > 0 || has_stored_tagged_photos(storage, &folder)?;
+        write_index(storage, &posts, child, has_tagged_photos, &folder)?;
+    }
+
+    write_family_index(storage, &posts, &children)?;
+    write_derived_files(storage, &children, &child_infos, &recorded, env)?;
+    export_archive(&children, &child_infos, &recorded, env)?;
+    notify_new_posts(&new_posts, env);
+    recorded.prune_redundant_runs()
+}
+
+/// Before hammering the API, makes sure the download folders can be created in principle.
+/// The folders keep the recorded API responses even if the archive is written elsewhere.
+fn prepare_folders(children: &[&ChildInfo], child_infos: &[ChildInfo], env: &Config) -> Result<()> {
+    for child in children {
+        let folder_name = child.get_folder_name();
+        create_dir(&env.output_dir.join(&folder_name))
+            .map_err(|e| format!("Cannot create the target folder: {0}", e))?;
+
+        if !matches!(env.storage, StorageConfig::Local) {
+            continue;
+        }
+
+        // The alias is a convenience only, so failing to create it is not fatal.
+        let alias = env.output_dir.join(child.get_alias(child_infos));
+        if let Err(e) = create_alias(&alias, &folder_name) {
+            println!("Cannot create the folder alias {0}: {1}", alias.display(), e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the configured storage the archive is written to.
+fn open_storage(env: &Config) -> Result<Box<dyn Storage>> {
+    let s3 = match &env.storage {
+        StorageConfig::Local => return Ok(Box::new(LocalStorage::new(&env.output_dir))),
+        StorageConfig::S3(s3) => s3,
+    };
+
+    let access_key_id = match &s3.access_key_id {
+        Some(id) => id.clone(),
+        None => std::env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| "No S3 access key: set AWS_ACCESS_KEY_ID or configure `access_key_id`")?,
+    };
+    let secret_access_key = match &s3.secret_access_key {
+        Some(source) => source.read().chain_err(|| "Cannot read the configured S3 secret access key")?,
+        None => std::env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| "No S3 secret key: set AWS_SECRET_ACCESS_KEY or configure `secret_access_key`")?,
+    };
+
+    let storage = S3Storage::new(&s3.endpoint, &s3.bucket, &s3.region, &s3.prefix, &access_key_id, &secret_access_key)?;
+    println!("Writing the archive to the bucket {0} at {1}", s3.bucket, s3.endpoint);
+    Ok(Box::new(storage))
+}
+
+/// Sends a summary of the new posts to the configured endpoints. As the posts are stored already,
+/// failing to notify is reported but not fatal.
+fn notify_new_posts(posts: &[NewPost], env: &Config) {
+    if posts.is_empty() || env.notify.is_empty() {
+        return;
+    }
+
+    println!("\nSending notifications about {0} new posts...", posts.len());
+    for config in &env.notify {
+        if let Err(e) = send_notification(config, posts) {
+            eprintln!("Cannot send the notification to {0}: {1}", config.url(), e);
+        }
+    }
+}
+
+fn send_notification(config: &NotifyConfig, posts: &[NewPost]) -> Result<()> {
+    let notifier = match config {
+        NotifyConfig::Webhook { url } => Notifier::Webhook { url: url.clone() },
+        NotifyConfig::Ntfy { url, token } => Notifier::Ntfy {
+            url: url.clone(),
+            token: token.as_ref().map(|t| t.read()).transpose().chain_err(|| "Cannot read the configured ntfy token")?,
+        },
+        NotifyConfig::Gotify { url, token } => Notifier::Gotify {
+            url: url.clone(),
+            token: token.read().chain_err(|| "Cannot read the configured Gotify token")?,
+        },
+    };
+    notifier.send(posts)?;
+    Ok(())
+}
+
+/// Copies the children's archive to the configured servers.
+fn export_archive(children: &[&ChildInfo], child_infos: &[ChildInfo], recorded: &RecordedPhotos, env: &Config)
+    -> Result<()> {
+    export_to_immich(children, child_infos, recorded, env)?;
+    upload_archive(children, env)
+}
+
+/// Exports the photos the children are tagged in, as recorded by this and previous runs, to the configured
+/// Immich server. Photos get an album per child and school year.
+fn export_to_immich(children: &[&ChildInfo], child_infos: &[ChildInfo], recorded: &RecordedPhotos, env: &Config)
+    -> Result<()> {
+    let immich_config = match &env.immich {
+        Some(immich_config) => immich_config,
+        None => return Ok(()),
+    };
+
+    println!("\nExporting photos to Immich at {0}...", immich_config.url);
+    let api_key = immich_config.api_key.read().chain_err(|| "Cannot read the configured Immich API key")?;
+    let client = ImmichClient::new(&immich_config.url, &api_key)?;
+
+    let mut photos = vec![];
+    for child in children {
+        let folder = child.get_folder_name();
+        // Unlike the first name, the alias tells children of the same name apart.
+        let name = child.get_alias(child_infos);
+        for photo in recorded.tagged_with(child).filter(|p| env.date_range.contains(&p.date)) {
+            photos.push(ExportedPhoto {
+                id: photo.id.clone(),
+                date: photo.date,
+                path: env.output_dir.join(&folder).join("tagged_photos").join(photo.get_file_name()),
+                description: recorded.descriptions.get(&photo.id).cloned(),
+                album: format!("{0} {1}", name, school_year(&photo.date, immich_config.school_year_start)),
+            });
+        }
+    }
+
+    let uploaded = immich::export(&client, &env.output_dir, &photos)?;
+    println!("{0} of {1} photos uploaded to Immich", uploaded, photos.len());
+    Ok(())
+}
+
+/// Posts and tagged photos recorded by this and previous runs. They are loaded once per run.
+struct RecordedPhotos {
+    posts: Vec<Post>,
+    /// Tagged photos by child ids.
+    tagged_photos: HashMap<String, Vec<Photo>>,
+    /// Texts of the posts by ids of their photos.
+    descriptions: HashMap<String, String>,
+    /// Recorded runs whose items are all recorded by a more recent run too.
+    redundant_runs: Vec<PathBuf>,
+}
+
+impl RecordedPhotos {
+    /// Loads the recorded posts of the children, their tagged photos are loaded by `load_tagged_photos`.
+    /// Posts recorded by several runs are taken from the most recent one.
+    fn load_posts(children: &[&ChildInfo], env: &Config) -> Result<RecordedPhotos> {
+        let child_ids: Vec<&String> = children.iter().map(|c| &c.id).collect();
+        let (posts, redundant_runs) = load_runs(&env.output_dir.join("raw").join("feed"),
+            |json| Post::from_feed_json(json, &child_ids))?;
+        let descriptions = posts.iter()
+            .flat_map(|post| post.photos.iter().map(|photo| (photo.id.clone(), post.text.clone())))
+            .collect();
+
+        Ok(RecordedPhotos { posts, tagged_photos: HashMap::new(), descriptions, redundant_runs })
+    }
+
+    fn load_tagged_photos(&mut self, child: &ChildInfo, env: &Config) -> Result<()> {
+        let (photos, redundant_runs) = load_runs(
+            &env.output_dir.join(child.get_folder_name()).join("raw").join("tagged_photos"), Photo::from_json_array)?;
+        self.tagged_photos.insert(child.id.clone(), photos);
+        self.redundant_runs.extend(redundant_runs);
+        Ok(())
+    }
+
+    /// Deletes the recorded runs that add nothing to the more recent ones. As every run fetches the feed again,
+    /// they would pile up otherwise.
+    fn prune_redundant_runs(&self) -> Result<()> {
+        for dir in &self.redundant_runs {
+            std::fs::remove_dir_all(dir)
+                .chain_err(|| format!("Cannot delete the recorded responses in {0}", dir.display()))?;
+        }
+        if !self.redundant_runs.is_empty() {
+            println!("{0} recorded runs covered by more recent ones deleted", self.redundant_runs.len());
+        }
+        Ok(())
+    }
+
+    /// Returns the tagged photos of all the children, without duplicates.
+    fn all_tagged_photos(&self, children: &[&ChildInfo]) -> Vec<&Photo> {
+        let mut seen_ids = HashSet::new();
+        children.iter()
+            .flat_map(|c| self.tagged_photos.get(&c.id).into_iter().flatten())
+            .filter(|p| seen_ids.insert(&p.id))
+            .collect()
+    }
+
+    /// Returns the photos the child is tagged in, from posts and the tagged photos, without duplicates.
+    fn tagged_with<'a>(&'a self, child: &'a ChildInfo) -> impl Iterator<Item = &'a Photo> + 'a {
+        let post_photos = self.posts.iter().flat_map(|p| &p.photos).filter(|p| p.is_tagged(&child.id));
+        let tagged_photos = self.tagged_photos.get(&child.id).into_iter().flatten();
+        let mut seen_ids = HashSet::new();
+        post_photos.chain(tagged_photos).filter(move |p| seen_ids.insert(&p.id))
+    }
+}
+
+/// Returns the items of all runs recorded in the folder, taken from the most recent run recording them, and the runs
+/// whose items are all recorded by a more recent run.
+fn load_runs<T: Identified, F>(root: &Path, parse: F) -> Result<(Vec<T>, Vec<PathBuf>)>
+    where F: Fn(String) -> post::Result<Page<T>>
+{
+    // Ids of all items, also the skipped ones, of the more recent runs.
+    let mut seen_ids = HashSet::new();
+    let mut item_ids = HashSet::new();
+    let mut items = vec![];
+    let mut redundant_runs = vec![];
+    for run in raw::list_runs(root)? {
+        let mut pages = vec![];
+        for path in &run.pages {
+            pages.push(parse(raw::load(path)?)
+                .chain_err(|| format!("Failed to deserialize the response recorded in {0}", path.display()))?);
+        }
+
+        if pages.iter().flat_map(|p| &p.ids).all(|id| seen_ids.contains(id)) {
+            redundant_runs.push(run.dir);
+            continue;
+        }
+        for page in pages {
+            seen_ids.extend(page.ids);
+            items.extend(page::dedupe(page.items, &mut item_ids));
+        }
+    }
+    Ok((items, redundant_runs))
+}
+
+/// Writes Google Takeout JSON sidecars next to the stored photos of the children, if enabled.
+fn write_sidecars(storage: &dyn Storage, children: &[&ChildInfo], child_infos: &[ChildInfo], recorded: &RecordedPhotos,
+    env: &Config) -> Result<()> {
+    if !env.include.takeout_sidecars {
+        return Ok(());
+    }
+
+    println!("\nWriting photo sidecars...");
+    let mut count = 0;
+    for child in children {
+        let folder = child.get_folder_name();
+        let stored: HashSet<String> = storage.list(&folder)?.into_iter().collect();
+
+        let post_photos = recorded.posts.iter().filter(|p| p.is_tagged(&child.id)).flat_map(|p| &p.photos);
+        let photos = post_photos.map(|p| ("posts/photos", p))
+            .chain(recorded.tagged_with(child).map(|p| ("tagged_photos", p)));
+        for (dir, photo) in photos {
+            if !stored.contains(&format!("{0}/{1}/{2}", folder, dir, photo.get_file_name())) {
+                continue;
+            }
+            let description = recorded.descriptions.get(&photo.id).map(String::as_str);
+            let sidecar = sidecar::render(photo, description, child_infos);
+            storage.put(&format!("{0}/{1}/{2}", folder, dir, sidecar::get_file_name(photo)), sidecar.as_bytes())?;
+            count += 1;
+        }
+    }
+
+    println!("{0} sidecars written", count);
+    Ok(())
+}
+
+/// Adds the recorded posts and photos of the children to the SQLite database in the output folder, if enabled.
+fn export_to_sqlite(children: &[&ChildInfo], child_infos: &[ChildInfo], recorded: &RecordedPhotos, env: &Config)
+    -> Result<()> {
+    if !env.include.sqlite {
+        return Ok(());
+    }
+
+    let path = env.output_dir.join(sqlite::FILE_NAME);
+    let tagged_photos = recorded.all_tagged_photos(children);
+    sqlite::export(&path, child_infos, &recorded.posts, &tagged_photos)?;
+    println!("\n{0} posts and {1} tagged photos exported to {2}", recorded.posts.len(), tagged_photos.len(), path.display());
+    Ok(())
+}
+
+/// Writes the recorded posts and photos of the children as JSON Lines and CSV to the export folder, if enabled.
+fn export_to_files(children: &[&ChildInfo], recorded: &RecordedPhotos, env: &Config) -> Result<()> {
+    if !env.include.jsonl && !env.include.csv {
+        return Ok(());
+    }
+
+    let dir = env.output_dir.join(export::DIR_NAME);
+    create_dir(&dir)?;
+    let mut posts: Vec<&Post> = recorded.posts.iter().collect();
+    posts.sort_by_key(|p| std::cmp::Reverse(p.date));
+    if env.include.jsonl {
+        export::write_jsonl(&dir, &posts)?;
+    }
+    if env.include.csv {
+        export::write_csv(&dir, &posts, &recorded.all_tagged_photos(children))?;
+    }
+    println!("\n{0} posts exported to {1}", posts.len(), dir.display());
+    Ok(())
+}
+
+/// Returns the school year of the date like `2023/24`, or just the year if the school year starts in January.
+fn school_year(date: &DateTime<Utc>, start_month: u32) -> String {
+    if start_month <= 1 {
+        return date.year().to_string();
+    }
+    let year = if date.month() >= start_month { date.year() } else { date.year() - 1 };
+    format!("{0}/{1:02}", year, (year + 1) % 100)
+}
+
+/// Uploads new and changed files of the children's archive to the configured target, if any.
+fn upload_archive(children: &[&ChildInfo], env: &Config) -> Result<()> {
+    let webdav = match &env.upload {
+        Some(UploadConfig::Webdav(webdav)) => webdav,
+        None => return Ok(()),
+    };
+
+    println!("\nUploading to {0}...", webdav.url);
+    let password = webdav.password.read().chain_err(|| "Cannot read the configured WebDAV password")?;
+    let target = WebDavFolder::new(&webdav.url, &webdav.username, &password)?;
+    let folders: Vec<String> = children.iter().map(|c| c.get_folder_name()).collect();
+    let uploaded = upload::upload(&env.output_dir, &folders, &target, &webdav.url)?;
+    println!("{0} files uploaded to {1}", uploaded, webdav.url);
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! A fake Famly API serving fixtures, so the whole sync can be tested without a Famly account.
+//! See `FixtureSource` for the layout of the fixtures folder, `{{BASE_URL}}` in the fixtures
+//! is replaced with the URL of the server, so photo links point to it.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use clap::Parser;
+use famly_dl::{FeedSource, FixtureSource};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Serves Famly API fixtures for local testing.
+#[derive(Parser)]
+struct Args {
+    /// Folder with the fixtures.
+    #[arg(long, value_name = "DIR")]
+    fixtures: PathBuf,
+
+    /// Port to listen on, 0 picks a free one. The URL is printed to the standard output once listening.
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+
+    /// The access token accepted by the server.
+    #[arg(long, default_value = "mock-token")]
+    token: String,
+
+    /// Email accepted by the login endpoint.
+    #[arg(long, default_value = "parent@example.com")]
+    email: String,
+
+    /// Password accepted by the login endpoint.
+    #[arg(long, default_value = "secret")]
+    password: String,
+
+    /// Number of items per page, regardless of the requested limit.
+    #[arg(long, default_value_t = 100)]
+    page_size: usize,
+
+    /// Makes the N-th request to the endpoint fail with the status, e.g. `feed:2:500`.
+    /// Endpoints: children, feed, tagged, image, login. Can be repeated.
+    #[arg(long = "fail", value_name = "ENDPOINT:N:STATUS")]
+    failures: Vec<String>,
+
+    /// Makes all image URLs behave as expired.
+    #[arg(long)]
+    expired_images: bool,
+}
+
+struct Failure {
+    endpoint: String,
+    nth: usize,
+    status: u16,
+}
+
+struct MockServer {
+    args: Args,
+    source: FixtureSource,
+    failures: Vec<Failure>,
+    /// Number of requests per endpoint so far.
+    calls: HashMap<&'static str, usize>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let failures = args.failures.iter().map(|f| parse_failure(f)).collect();
+
+    let server = Server::http(("127.0.0.1", args.port)).expect("Failed to start the server");
+    let port = server.server_addr().to_ip().expect("Not an IP address").port();
+    let base_url = format!("http://127.0.0.1:{}", port);
+    println!("{}", base_url);
+
+    let source = FixtureSource::new(&args.fixtures, &base_url, args.page_size);
+    let mut mock = MockServer { args, source, failures, calls: HashMap::new() };
+    for request in server.incoming_requests() {
+        mock.handle(request);
+    }
+}
+
+fn parse_failure(value: &str) -> Failure {
+    let parts: Vec<&str> = value.split(':').collect();
+    match parts.as_slice() {
+        [endpoint, nth, status] => Failure {
+            endpoint: endpoint.to_string(),
+            nth: nth.parse().expect("N must be a number"),
+            status: status.parse().expect("STATUS must be a number"),
+        },
+        _ => panic!("Failure must look like ENDPOINT:N:STATUS, got '{}'", value),
+    }
+}
+
+impl MockServer {
+    fn handle(&mut self, mut request: Request) {
+        let (path, query) = split_url(request.url());
+        eprintln!("{} {}", request.method(), request.url());
+
+        let endpoint = match (request.method(), path.as_str()) {
+            (Method::Get, "/api/v2/calendar/list") => "children",
+            (Method::Get, "/api/feed/feed/feed") => "feed",
+            (Method::Get, "/api/v2/images/tagged") => "tagged",
+            (Method::Post, "/graphql") => "login",
+            (Method::Get, p) if p.starts_with("/images/") => "image",
+            _ => return respond(request, 404, "Not found"),
+        };
+
+        let calls = self.calls.entry(endpoint).or_insert(0);
+        *calls += 1;
+        let nth = *calls;
+        if let Some(f) = self.failures.iter().find(|f| f.endpoint == endpoint && f.nth == nth) {
+            return respond(request, f.status, "Injected failure");
+        }
+
+        let response = match endpoint {
+            "image" => return self.serve_image(request),
+            "login" => {
+                let mut body = String::new();
+                let _ = request.as_reader().read_to_string(&mut body);
+                return respond_json(request, self.log_in(&body));
+            },
+            _ if !self.is_authorized(&request) => return respond(request, 401, "Unauthorized"),
+            "children" => self.source.child_infos_json(),
+            "feed" => self.source.feed_page(&query.get("olderThan").cloned()),
+            _ => {
+                let child_id = query.get("childId").cloned().unwrap_or_default();
+                self.source.tagged_photos_page(&child_id, &query.get("olderThan").cloned())
+            },
+        };
+
+        match response {
+            Ok(json) => {
+                let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+                let _ = request.respond(Response::from_string(json).with_header(header));
+            },
+            Err(e) => respond(request, 500, &e.to_string()),
+        }
+    }
+
+    fn is_authorized(&self, request: &Request) -> bool {
+        request.headers().iter()
+            .any(|h| h.field.equiv("x-famly-accesstoken") && h.value.as_str() == self.args.token)
+    }
+
+    fn log_in(&self, body: &str) -> Value {
+        let request: Value = serde_json::from_str(body).unwrap_or_default();
+        let variables = &request["variables"];
+        let result = if variables["email"] == self.args.email.as_str() && variables["password"] == self.args.password.as_str() {
+            json!({ "__typename": "AuthenticationSucceeded", "accessToken": self.args.token })
+        } else {
+            json!({ "__typename": "AuthenticationFailed", "errorDetails": "Wrong email or password" })
+        };
+        json!({ "data": { "me": { "authenticateWithPassword": result } } })
+    }
+
+    fn serve_image(&self, request: Request) {
+        if self.args.expired_images {
+            return respond(request, 403, "Request has expired");
+        }
+
+        let mut bytes = vec![];
+        match self.source.download_image(request.url(), &mut bytes) {
+            Ok(()) => {
+                let header = Header::from_bytes("Content-Type", "image/jpeg").unwrap();
+                let _ = request.respond(Response::from_data(bytes).with_header(header));
+            },
+            Err(e) => respond(request, 404, &e.to_string()),
+        }
+    }
+}
+
+fn split_url(url: &str) -> (String, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), urlencoding::decode(v).map(|v| v.into_owned()).unwrap_or_default()))
+        .collect();
+    (path.to_string(), query)
+}
+
+fn respond(request: Request, status: u16, text: &str) {
+    let mut response = Response::from_string(text).with_status_code(status);
+    if status == 429 {
+        response.add_header(Header::from_bytes("Retry-After", "0").unwrap());
+    }
+    let _ = request.respond(response);
+}
+
+fn respond_json(request: Request, value: Value) {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let _ = request.respond(Response::from_string(value.to_string()).with_header(header));
+}
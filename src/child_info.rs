@@ -1,7 +1,9 @@
 use serde_json::Value;
 use error_chain::error_chain;
 
+use crate::config::ChildSelection;
 use crate::json::parse_string;
+use crate::{export, search_index, sqlite};
 
 error_chain! {
     foreign_links {
@@ -9,6 +11,9 @@ error_chain! {
     }
 }
 
+/// Entries of the output folder next to the child folders and their aliases.
+const RESERVED_NAMES: [&str; 5] = ["raw", export::DIR_NAME, "index.htm", search_index::FILE_NAME, sqlite::FILE_NAME];
+
 pub struct ChildInfo {
     pub id: String,
     pub full_name_with_institution: String,
@@ -25,6 +30,49 @@ impl ChildInfo {
             self.full_name_with_institution.clone()
         }        
     }
+
+    /// Returns the name of the child's archive folder. It is based on the id only,
+    /// so it stays the same when the child is renamed.
+    pub fn get_folder_name(&self) -> String {
+        self.id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect()
+    }
+
+    /// Returns the human-readable alias of the child's archive folder: the first name,
+    /// extended with a part of the id if another child has the same first name or it is taken
+    /// by another entry of the output folder. The name is sanitized, as it becomes part of a path.
+    pub fn get_alias(&self, all: &[ChildInfo]) -> String {
+        let first_name = self.get_safe_first_name();
+        if first_name.is_empty() {
+            return self.get_folder_name();
+        }
+        // Some file systems ignore the case.
+        let is_ambiguous = all.iter().any(|c| c.id != self.id && c.get_safe_first_name() == first_name)
+            || RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(&first_name));
+
+        if is_ambiguous {
+            let short_id: String = self.get_folder_name().chars().take(8).collect();
+            format!("{}_{}", first_name, short_id)
+        } else {
+            first_name
+        }
+    }
+
+    /// Returns the first name with everything but letters, digits and `-` replaced by `_`.
+    fn get_safe_first_name(&self) -> String {
+        self.get_first_name().chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+            .collect()
+    }
+
+    /// Returns true if the selector is the id, the first name or the full name of the child.
+    fn matches(&self, selector: &str) -> bool {
+        let selector = selector.trim().to_lowercase();
+        self.id.to_lowercase() == selector
+            || self.get_first_name().to_lowercase() == selector
+            || self.full_name_with_institution.to_lowercase() == selector
+    }
 }
 
 pub fn from_json(json: String) -> Result<Vec<ChildInfo>> {
@@ -40,4 +88,64 @@ pub fn from_json(json: String) -> Result<Vec<ChildInfo>> {
         })        
     }
     Ok(res)
-}
\ No newline at end of file
+}
+
+/// Picks the children described by the selection, failing if any part of it
+/// matches no child or is ambiguous.
+pub fn select<'a>(child_infos: &'a [ChildInfo], selection: &ChildSelection) -> Result<Vec<&'a ChildInfo>> {
+    if selection.all {
+        return Ok(child_infos.iter().collect());
+    }
+
+    let mut candidates: Vec<&ChildInfo> = child_infos.iter().collect();
+    if !selection.institutions.is_empty() {
+        for title in &selection.institutions {
+            if !child_infos.iter().any(|c| c.institution.eq_ignore_ascii_case(title)) {
+                return Err(format!("No children found in institution '{0}'. Known institutions: {1}",
+                    title, describe_institutions(child_infos)).into());
+            }
+        }
+        candidates.retain(|c| selection.institutions.iter().any(|i| c.institution.eq_ignore_ascii_case(i)));
+    }
+
+    if selection.children.is_empty() {
+        return Ok(candidates);
+    }
+
+    let mut selected: Vec<&ChildInfo> = vec![];
+    for selector in &selection.children {
+        let matches: Vec<&ChildInfo> = candidates.iter()
+            .copied()
+            .filter(|c| c.matches(selector))
+            .collect();
+
+        match matches.as_slice() {
+            [] => return Err(format!("No child matches '{0}'. Known children: {1}",
+                selector, describe_children(&candidates)).into()),
+            [child] => {
+                if !selected.iter().any(|c| c.id == child.id) {
+                    selected.push(child);
+                }
+            },
+            _ => return Err(format!(
+                "'{0}' matches several children: {1}. Use the child id or the institution to pick one",
+                selector, describe_children(&matches)).into()),
+        }
+    }
+
+    Ok(selected)
+}
+
+fn describe_children(children: &[&ChildInfo]) -> String {
+    children.iter()
+        .map(|c| format!("{0} ({1}, {2})", c.full_name_with_institution, c.institution, c.id))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn describe_institutions(child_infos: &[ChildInfo]) -> String {
+    let mut titles: Vec<&str> = child_infos.iter().map(|c| c.institution.as_str()).collect();
+    titles.sort();
+    titles.dedup();
+    titles.join("; ")
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+/// Downloads Famly posts and photos of your children.
+#[derive(Parser)]
+#[command(version)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file to use instead of the default one.
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Profile of the configuration file to use.
+    #[arg(long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
+
+    /// Folder where archives of all children are created.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Child to download: its id, first name or full name. Can be repeated.
+    #[arg(long = "child", value_name = "CHILD", global = true)]
+    pub children: Vec<String>,
+
+    /// Restricts the selection to children of the institution with this title.
+    /// Without `--child` selects all children of the institution. Can be repeated.
+    #[arg(long = "institution", value_name = "TITLE", global = true)]
+    pub institutions: Vec<String>,
+
+    /// Downloads all children.
+    #[arg(long, conflicts_with_all = ["children", "institutions"], global = true)]
+    pub all_children: bool,
+
+    /// Downloads only items created on or after this date (YYYY-MM-DD or an RFC 3339 timestamp).
+    #[arg(long, value_name = "DATE", global = true)]
+    pub since: Option<String>,
+
+    /// Downloads only items created on or before this date (YYYY-MM-DD or an RFC 3339 timestamp).
+    #[arg(long, value_name = "DATE", global = true)]
+    pub until: Option<String>,
+
+    /// Doesn't download posts.
+    #[arg(long, global = true)]
+    pub skip_posts: bool,
+
+    /// Doesn't download tagged photos.
+    #[arg(long, global = true)]
+    pub skip_tagged_photos: bool,
+
+    /// Writes Google Takeout JSON sidecars next to the photos for photo importers.
+    #[arg(long, global = true)]
+    pub takeout_sidecars: bool,
+
+    /// Exports posts, photos and comments to the SQLite database `famly.sqlite` in the output folder.
+    #[arg(long, global = true)]
+    pub sqlite: bool,
+
+    /// Exports posts with their photos and comments to `export/posts.jsonl` in the output folder.
+    #[arg(long, global = true)]
+    pub jsonl: bool,
+
+    /// Exports posts, photos and comments to CSV tables in `export/` in the output folder.
+    #[arg(long, global = true)]
+    pub csv: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Encrypts an access token with a passphrase and stores it in a token cache file.
+    /// The token is read from the standard input.
+    StoreToken {
+        /// The token cache file to create.
+        file: PathBuf,
+    },
+
+    /// Regenerates the archive from API responses recorded by previous runs, without network access.
+    Rebuild,
+
+    /// Imports posts and tagged photos from a HAR file saved with browser DevTools while scrolling the feed.
+    /// No access token is needed.
+    ImportHar {
+        /// The HAR file to import.
+        file: PathBuf,
+    },
+
+    /// Emails the posts that are new since the last digest to the configured recipients.
+    /// Works on the responses recorded by previous runs, so run it after a sync.
+    Digest,
+
+    /// Keeps running and syncs on a schedule until stopped with SIGTERM or Ctrl+C.
+    /// The status of the last sync is served as JSON over HTTP.
+    Daemon {
+        /// Time between the starts of syncs, e.g. 30m, 6h or 1d. The first sync starts right away.
+        #[arg(long, value_name = "INTERVAL", conflicts_with = "cron")]
+        interval: Option<String>,
+
+        /// Cron expression in local time of when to sync, e.g. "0 3 * * *".
+        #[arg(long, value_name = "EXPRESSION")]
+        cron: Option<String>,
+
+        /// Address of the status endpoint [default: 127.0.0.1:8787].
+        #[arg(long, value_name = "ADDRESS")]
+        status_address: Option<String>,
+    },
+
+    /// Serves the archive over HTTP, with a search over posts and comments at /search.
+    /// Photos get thumbnails with `?thumbnail=<SIZE>`.
+    Serve {
+        /// Address to listen at, use 0.0.0.0:8080 to serve the local network.
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+}
@@ -0,0 +1,72 @@
+use reqwest::blocking::Client;
+use std::io::Write;
+
+use crate::http;
+use crate::source::FeedSource;
+
+pub use crate::http::{Error, ErrorKind, Result, DEFAULT_API_URL};
+
+/// An authenticated connection to a Famly instance, returning typed children, posts and photos.
+///
+/// ```no_run
+/// use famly_dl::{DateRange, FamlyClient, FeedSource};
+/// use famly_dl::client::DEFAULT_API_URL;
+///
+/// let client = FamlyClient::new("access token", DEFAULT_API_URL)?;
+/// let children = client.child_infos()?;
+/// let child_ids: Vec<&String> = children.iter().map(|c| &c.id).collect();
+/// for page in client.posts(&child_ids, &DateRange::default()) {
+///     for post in page? {
+///         println!("{} {}", post.date, post.get_title(false));
+///     }
+/// }
+/// # Ok::<(), famly_dl::client::Error>(())
+/// ```
+pub struct FamlyClient {
+    client: Client,
+    api_url: String,
+}
+
+impl FamlyClient {
+    /// Creates a client using the access token, `api_url` is e.g. `DEFAULT_API_URL`.
+    pub fn new(access_token: &str, api_url: &str) -> Result<FamlyClient> {
+        let api_url = api_url.trim_end_matches('/').to_string();
+        let client = http::create_web_client(access_token.to_string(), &api_url)?;
+        Ok(FamlyClient { client, api_url })
+    }
+
+    /// Logs in with the account credentials to get a new access token.
+    pub fn log_in(api_url: &str, email: &str, password: &str) -> Result<FamlyClient> {
+        let token = http::log_in(api_url.trim_end_matches('/'), email, password)?;
+        FamlyClient::new(&token, api_url)
+    }
+
+    /// Creates a client without an access token, it can only download photos through their signed links,
+    /// e.g. the ones saved in a HAR file.
+    pub fn anonymous(api_url: &str) -> FamlyClient {
+        FamlyClient { client: Client::new(), api_url: api_url.trim_end_matches('/').to_string() }
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+}
+
+impl FeedSource for FamlyClient {
+    fn child_infos_json(&self) -> Result<String> {
+        http::fetch_child_infos(&self.client, &self.api_url)
+    }
+
+    fn feed_page(&self, older_than: &Option<String>) -> Result<String> {
+        http::fetch_feed(&self.client, &self.api_url, older_than)
+    }
+
+    fn tagged_photos_page(&self, child_id: &str, older_than: &Option<String>) -> Result<String> {
+        http::fetch_tagged_photos(&self.client, &self.api_url, child_id, older_than)
+    }
+
+    /// Photo links expire after a while, so download them soon after fetching.
+    fn download_image(&self, url: &str, writer: &mut dyn Write) -> Result<()> {
+        http::download_image(url, writer)
+    }
+}
@@ -1,15 +1,464 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::path::{Path, PathBuf};
+
+use error_chain::error_chain;
+use serde::Deserialize;
+
+use crate::cli::Args;
+use crate::date_range::{self, DateRange};
+use crate::http;
+use crate::smtp;
+use crate::token::TokenSource;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Toml(toml::de::Error);
+    }
+}
 
 pub struct Config {
-    pub access_token: String,
-    pub default_target_folder: Option<String>,
+    /// `None` if no token is configured. It's read only when needed, as reading may require user's input.
+    token: Option<TokenSource>,
+    /// Credentials to get a new access token when the configured one is expired.
+    pub login: Option<Login>,
+    /// Base URL of the Famly API, it differs between Famly instances.
+    pub api_url: String,
+    /// The folder where archives of all children are created.
+    pub output_dir: PathBuf,
+    pub child_selection: ChildSelection,
+    pub include: Include,
+    pub date_range: DateRange,
+    /// Where the archive is written, the output folder still keeps the recorded API responses.
+    pub storage: StorageConfig,
+    /// Where the archive is uploaded to after it's written to the output folder.
+    pub upload: Option<UploadConfig>,
+    /// The Immich server tagged photos are exported to.
+    pub immich: Option<ImmichConfig>,
+    /// Where notifications about new posts are sent after a sync.
+    pub notify: Vec<NotifyConfig>,
+    /// How email digests of new posts are sent.
+    pub digest: Option<DigestConfig>,
+    /// Schedule and status endpoint of the daemon, the command line overrides them.
+    pub daemon: DaemonConfig,
+    /// Describes where the configuration comes from.
+    origin: String,
+}
+
+/// Describes which children to download without asking the user.
+#[derive(Default)]
+pub struct ChildSelection {
+    pub all: bool,
+    /// Ids, first names or full names.
+    pub children: Vec<String>,
+    /// Institution titles.
+    pub institutions: Vec<String>,
+}
+
+impl ChildSelection {
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.children.is_empty() && self.institutions.is_empty()
+    }
+}
+
+/// Describes what kind of content gets downloaded.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Include {
+    pub posts: bool,
+    pub tagged_photos: bool,
+    /// Google Takeout JSON sidecars next to the photos.
+    pub takeout_sidecars: bool,
+    /// The SQLite database of posts, photos and comments in the output folder.
+    pub sqlite: bool,
+    /// `posts.jsonl` in the export folder.
+    pub jsonl: bool,
+    /// `posts.csv`, `photos.csv` and `comments.csv` in the export folder.
+    pub csv: bool,
+}
+
+impl Default for Include {
+    fn default() -> Self {
+        Include { posts: true, tagged_photos: true, takeout_sidecars: false, sqlite: false, jsonl: false, csv: false }
+    }
+}
+
+/// Famly account credentials.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Login {
+    pub email: String,
+    /// Supports the same sources as the access token.
+    pub password: TokenSource,
+}
+
+/// Where the archive is written.
+#[derive(Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StorageConfig {
+    /// The output folder.
+    #[default]
+    Local,
+    S3(S3Config),
+}
+
+/// A bucket of an S3-compatible object storage.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    /// E.g. `https://s3.eu-central-1.amazonaws.com` or `http://localhost:9000` for a local MinIO.
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Folder in the bucket to write the archive to.
+    #[serde(default)]
+    pub prefix: String,
+    /// Taken from `AWS_ACCESS_KEY_ID` if not configured.
+    pub access_key_id: Option<String>,
+    /// Supports the same sources as the access token, taken from `AWS_SECRET_ACCESS_KEY` if not configured.
+    pub secret_access_key: Option<TokenSource>,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Where the archive is uploaded to.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UploadConfig {
+    Webdav(WebDavConfig),
+}
+
+/// A folder on a WebDAV server, e.g. Nextcloud.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebDavConfig {
+    /// E.g. `https://cloud.example.com/remote.php/dav/files/<user>/Famly`.
+    pub url: String,
+    pub username: String,
+    /// Supports the same sources as the access token.
+    pub password: TokenSource,
+}
+
+/// An Immich server and how photos are organized there.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ImmichConfig {
+    /// E.g. `http://localhost:2283`.
+    pub url: String,
+    /// Supports the same sources as the access token.
+    pub api_key: TokenSource,
+    /// Month the school year starts with, photos get an album per child and school year.
+    #[serde(default = "default_school_year_start")]
+    pub school_year_start: u32,
+}
+
+fn default_school_year_start() -> u32 {
+    8
+}
+
+/// An endpoint notifications about new posts are sent to.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum NotifyConfig {
+    /// Receives the new posts as JSON.
+    Webhook { url: String },
+    /// E.g. `https://ntfy.sh/famly-posts`.
+    Ntfy { url: String, token: Option<TokenSource> },
+    /// The token of a Gotify application.
+    Gotify { url: String, token: TokenSource },
+}
+
+impl NotifyConfig {
+    pub fn url(&self) -> &str {
+        match self {
+            NotifyConfig::Webhook { url } | NotifyConfig::Ntfy { url, .. } | NotifyConfig::Gotify { url, .. } => url,
+        }
+    }
+}
+
+/// An SMTP server and the recipients of email digests.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DigestConfig {
+    pub smtp_host: String,
+    /// Usually 587 with `starttls` and 465 with `tls`.
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub security: smtp::Security,
+    pub username: Option<String>,
+    /// Supports the same sources as the access token.
+    pub password: Option<TokenSource>,
+    /// E.g. `Famly <famly@example.com>`.
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+/// When the daemon syncs: either an interval like `6h` or a cron expression like `0 3 * * *`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub interval: Option<String>,
+    pub cron: Option<String>,
+    /// E.g. `127.0.0.1:8787`.
+    pub status_address: Option<String>,
+}
+
+/// The content of the configuration file.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings of a single profile, e.g. one per parent account.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    token: Option<TokenSource>,
+    login: Option<Login>,
+    api_url: Option<String>,
+    output_dir: Option<PathBuf>,
+    #[serde(default)]
+    all_children: bool,
+    #[serde(default)]
+    children: Vec<String>,
+    #[serde(default)]
+    institutions: Vec<String>,
+    #[serde(default)]
+    include: Include,
+    /// Lower bound of the date range, a date or an RFC 3339 timestamp.
+    since: Option<String>,
+    /// Upper bound of the date range, a date or an RFC 3339 timestamp.
+    until: Option<String>,
+    #[serde(default)]
+    storage: StorageConfig,
+    upload: Option<UploadConfig>,
+    immich: Option<ImmichConfig>,
+    #[serde(default)]
+    notify: Vec<NotifyConfig>,
+    digest: Option<DigestConfig>,
+    #[serde(default)]
+    daemon: DaemonConfig,
 }
 
 impl Config {
-    pub fn new() -> Config {
-        let access_token = env::var("FAMLY_ACCESS_TOKEN").unwrap();
-        let default_target_folder = env::var("FAMLY_TARGET_FOLDER").ok();
+    /// Returns the configured access token or `None` if there is none, but the login is configured.
+    pub fn read_access_token(&self) -> Result<Option<String>> {
+        let token = match &self.token {
+            Some(source) => source.read()
+                .chain_err(|| format!("Cannot read the access token configured in {0}", self.origin))?,
+            None if self.login.is_some() => return Ok(None),
+            None => return Err(format!(
+                "No access token: set FAMLY_ACCESS_TOKEN or configure `token` or `login` in {0}", self.origin).into()),
+        };
+        if token.trim().is_empty() {
+            return Err("The access token is empty".into());
+        }
+        Ok(Some(token))
+    }
+
+    /// Reads the configuration from the profile of the configuration file,
+    /// environment variables override it and command line arguments override both.
+    pub fn new(args: &Args) -> Result<Config> {
+        let (profile, origin) = load_profile(args)?;
+
+        let token = match env::var("FAMLY_ACCESS_TOKEN") {
+            Ok(token) => Some(TokenSource::Value(token)),
+            Err(_) => profile.token.clone(),
+        };
+        let login = profile.login.clone();
+
+        let api_url = env::var("FAMLY_API_URL").ok()
+            .or_else(|| profile.api_url.clone())
+            .unwrap_or_else(|| http::DEFAULT_API_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        let output_dir = args.output_dir.clone()
+            .or_else(|| env::var("FAMLY_TARGET_FOLDER").ok().map(PathBuf::from))
+            .or_else(|| profile.output_dir.clone())
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let child_selection = if args.all_children || !args.children.is_empty() || !args.institutions.is_empty() {
+            ChildSelection {
+                all: args.all_children,
+                children: args.children.clone(),
+                institutions: args.institutions.clone(),
+            }
+        } else if env::var("FAMLY_ALL_CHILDREN").is_ok()
+            || env::var("FAMLY_CHILD").is_ok()
+            || env::var("FAMLY_INSTITUTION").is_ok() {
+            ChildSelection {
+                all: env::var("FAMLY_ALL_CHILDREN").map(|v| v == "1" || v == "true").unwrap_or(false),
+                children: split_list(env::var("FAMLY_CHILD").ok()),
+                institutions: split_list(env::var("FAMLY_INSTITUTION").ok()),
+            }
+        } else {
+            ChildSelection {
+                all: profile.all_children,
+                children: profile.children.clone(),
+                institutions: profile.institutions.clone(),
+            }
+        };
+        if child_selection.all && !(child_selection.children.is_empty() && child_selection.institutions.is_empty()) {
+            return Err(format!(
+                "`all_children` cannot be combined with `children` or `institutions` in {0}", origin).into());
+        }
+
+        let mut include = profile.include;
+        if args.skip_posts {
+            include.posts = false;
+        }
+        if args.skip_tagged_photos {
+            include.tagged_photos = false;
+        }
+        if args.takeout_sidecars {
+            include.takeout_sidecars = true;
+        }
+        if args.sqlite {
+            include.sqlite = true;
+        }
+        if args.jsonl {
+            include.jsonl = true;
+        }
+        if args.csv {
+            include.csv = true;
+        }
+        if !include.posts && !include.tagged_photos {
+            return Err("Nothing to download: both posts and tagged photos are excluded".into());
+        }
+
+        let since = args.since.clone()
+            .or_else(|| env::var("FAMLY_SINCE").ok())
+            .or_else(|| profile.since.clone());
+        let until = args.until.clone()
+            .or_else(|| env::var("FAMLY_UNTIL").ok())
+            .or_else(|| profile.until.clone());
+        let date_range = DateRange {
+            since: since.map(|v| date_range::parse_bound(&v, false)).transpose()
+                .map_err(|e| format!("Invalid lower bound of the date range: {0}", e))?,
+            until: until.map(|v| date_range::parse_bound(&v, true)).transpose()
+                .map_err(|e| format!("Invalid upper bound of the date range: {0}", e))?,
+        };
+        if let (Some(since), Some(until)) = (date_range.since, date_range.until) {
+            if since > until {
+                return Err(format!("The date range is empty: {0} is after {1}", since, until).into());
+            }
+        }
+
+        let storage = profile.storage.clone();
+        let upload = profile.upload.clone();
+        let immich = profile.immich.clone();
+        let digest = profile.digest.clone();
+        for (name, is_set) in [("upload", upload.is_some()), ("immich", immich.is_some()), ("digest", digest.is_some())] {
+            if is_set && !matches!(storage, StorageConfig::Local) {
+                return Err(format!("`{0}` requires the archive to be written to the output folder in {1}", name, origin).into());
+            }
+        }
+        if let Some(month) = immich.as_ref().map(|i| i.school_year_start).filter(|m| !(1..=12).contains(m)) {
+            return Err(format!("Invalid `school_year_start` {0} in {1}, expected a month from 1 to 12", month, origin).into());
+        }
+
+        if let Some(digest) = &digest {
+            if digest.to.is_empty() {
+                return Err(format!("The digest has no recipients (`to`) in {0}", origin).into());
+            }
+            if digest.password.is_some() && digest.username.is_none() {
+                return Err(format!("The digest has an SMTP `password` but no `username` in {0}", origin).into());
+            }
+        }
+        let notify = profile.notify.clone();
+
+        let daemon = profile.daemon.clone();
+        if daemon.interval.is_some() && daemon.cron.is_some() {
+            return Err(format!("The daemon has both an `interval` and a `cron` schedule in {0}", origin).into());
+        }
 
-        Config { access_token, default_target_folder }
+        Ok(Config {
+            token, login, api_url, output_dir, child_selection, include, date_range,
+            storage, upload, immich, notify, digest, daemon, origin,
+        })
     }
-}
\ No newline at end of file
+}
+
+/// Returns the path of the configuration file: the one passed explicitly (it must exist)
+/// or the default one in the user's configuration folder (it may be missing).
+fn config_file_path(args: &Args) -> Option<(PathBuf, bool)> {
+    if let Some(path) = &args.config {
+        return Some((path.clone(), true));
+    }
+    if let Ok(path) = env::var("FAMLY_CONFIG") {
+        return Some((PathBuf::from(path), true));
+    }
+    dirs::config_dir().map(|d| (d.join("famly-dl").join("config.toml"), false))
+}
+
+/// Loads the selected profile and returns it together with a description of where it comes from.
+fn load_profile(args: &Args) -> Result<(Profile, String)> {
+    let file = match config_file_path(args) {
+        Some((path, must_exist)) if must_exist || path.exists() => Some((read_config_file(&path)?, path)),
+        _ => None,
+    };
+
+    let profile_name = args.profile.clone().or_else(|| env::var("FAMLY_PROFILE").ok());
+
+    let (file, path) = match file {
+        Some(f) => f,
+        None => {
+            if let Some(name) = profile_name {
+                return Err(format!("Profile '{0}' is requested, but no configuration file is found", name).into());
+            }
+            return Ok((Profile::default(), "a configuration file profile".to_string()));
+        },
+    };
+
+    let profile_name = profile_name
+        .or(file.default_profile)
+        .or_else(|| if file.profiles.len() == 1 { file.profiles.keys().next().cloned() } else { None });
+
+    match profile_name {
+        Some(name) => match file.profiles.get(&name) {
+            Some(profile) => Ok((profile.clone(), format!("profile '{0}' of {1}", name, path.display()))),
+            None => Err(format!(
+                "Profile '{0}' is not found in {1}. Available profiles: {2}",
+                name, path.display(), describe_profiles(&file.profiles)).into()),
+        },
+        None if file.profiles.is_empty() => Ok((Profile::default(), path.display().to_string())),
+        None => Err(format!(
+            "Several profiles are configured in {0}, pick one with --profile or `default_profile`. Available profiles: {1}",
+            path.display(), describe_profiles(&file.profiles)).into()),
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile> {
+    let content = std::fs::read_to_string(path)
+        .chain_err(|| format!("Cannot read the configuration file {0}", path.display()))?;
+    let file = toml::from_str(&content)
+        .chain_err(|| format!("Invalid configuration file {0}", path.display()))?;
+    Ok(file)
+}
+
+fn describe_profiles(profiles: &BTreeMap<String, Profile>) -> String {
+    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+}
+
+/// Splits a comma-separated environment variable value.
+fn split_list(value: Option<String>) -> Vec<String> {
+    value
+        .map(|v| v.split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect())
+        .unwrap_or_default()
+}
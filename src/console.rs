@@ -1,20 +1,39 @@
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
-pub fn choose_number(question: &str, max: usize) -> Option<usize> {
+/// Returns true if the user can be asked questions.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+/// Asks a yes/no question, an empty answer means yes.
+pub fn confirm(question: &str) -> std::io::Result<bool> {
+    print!("{}", question);
+
+    let mut answer_raw = String::new();
+    std::io::stdout().flush()?;
+    if std::io::stdin().read_line(&mut answer_raw)? == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Input is closed"));
+    }
+
+    let answer = answer_raw.trim().to_lowercase();
+    Ok(answer.is_empty() || answer == "y" || answer == "yes")
+}
+
+pub fn choose_number(question: &str, min: usize, max: usize) -> std::io::Result<Option<usize>> {
     print!("{}", question);
     
     let mut answer_raw = String::new();
-    std::io::stdout().flush().unwrap();
-    std::io::stdin()
-        .read_line(&mut answer_raw)
-        .expect("Failed to read input");
+    std::io::stdout().flush()?;
+    if std::io::stdin().read_line(&mut answer_raw)? == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Input is closed"));
+    }
     let answer = answer_raw.trim_end();
 
-    if let Some(res) = answer.parse().ok() {
-        if res > 0 && res <= max {
-            return Some(res);
+    if let Ok(res) = answer.parse() {
+        if res >= min && res <= max {
+            return Ok(Some(res));
         }
     }
     
-    None
-}
\ No newline at end of file
+    Ok(None)
+}
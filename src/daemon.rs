@@ -0,0 +1,140 @@
+//! Runs syncs on a schedule and serves the status of the last one over HTTP.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::{DateTime, Local};
+use error_chain::error_chain;
+use serde::Serialize;
+use tiny_http::{Header, Response, Server};
+
+use crate::schedule::Schedule;
+use crate::shutdown;
+
+error_chain! {}
+
+/// The status served as JSON.
+#[derive(Serialize)]
+struct Status {
+    /// `waiting` or `running`.
+    state: &'static str,
+    schedule: String,
+    next_run: Option<String>,
+    last_run: Option<LastRun>,
+    runs: u32,
+    failures: u32,
+}
+
+#[derive(Serialize)]
+struct LastRun {
+    started_at: String,
+    finished_at: String,
+    success: bool,
+    error: Option<String>,
+}
+
+/// Calls `sync` on the schedule until a shutdown is requested. A sync in progress is stopped by `sync` itself,
+/// it's expected to check for the shutdown between items. Syncs never overlap, a late one delays the next one.
+pub fn run<F>(schedule: &Schedule, status_address: &str, mut sync: F) -> Result<()>
+    where F: FnMut() -> std::result::Result<(), String>
+{
+    let status = Arc::new(Mutex::new(Status {
+        state: "waiting",
+        schedule: schedule.to_string(),
+        next_run: None,
+        last_run: None,
+        runs: 0,
+        failures: 0,
+    }));
+    let server = serve_status(status_address, status.clone())?;
+    println!("Syncing {0}, the status is served at http://{1}/status", schedule, server.server_addr());
+
+    let mut last_start = None;
+    let result = loop {
+        let next_run = match schedule.next_run(last_start, Local::now()) {
+            Some(next_run) => next_run,
+            None => break Err(format!("The schedule {0} never runs", schedule).into()),
+        };
+        status.lock().unwrap().next_run = Some(next_run.to_rfc3339());
+        if next_run > Local::now() {
+            println!("\nNext sync at {0}", next_run.format("%Y-%m-%d %H:%M"));
+        }
+        if !wait_until(next_run) {
+            break Ok(());
+        }
+
+        let started_at = Local::now();
+        last_start = Some(started_at);
+        {
+            let mut status = status.lock().unwrap();
+            status.state = "running";
+            status.next_run = None;
+        }
+        println!("\nSync started at {0}", started_at.format("%Y-%m-%d %H:%M:%S"));
+
+        let error = sync().err();
+        match &error {
+            Some(e) => println!("The sync failed: {0}", e),
+            None => println!("The sync finished"),
+        }
+        let mut status = status.lock().unwrap();
+        status.state = "waiting";
+        status.runs += 1;
+        status.failures += u32::from(error.is_some());
+        status.last_run = Some(LastRun {
+            started_at: started_at.to_rfc3339(),
+            finished_at: Local::now().to_rfc3339(),
+            success: error.is_none(),
+            error,
+        });
+
+        if shutdown::is_requested() {
+            break Ok(());
+        }
+    };
+
+    server.unblock();
+    if result.is_ok() {
+        println!("Stopped");
+    }
+    result
+}
+
+/// Sleeps until the time, returns false if a shutdown is requested meanwhile.
+fn wait_until(time: DateTime<Local>) -> bool {
+    loop {
+        if shutdown::is_requested() {
+            return false;
+        }
+        let remaining = time - Local::now();
+        if remaining <= chrono::Duration::zero() {
+            return true;
+        }
+        let step = remaining.to_std().unwrap_or_default().min(std::time::Duration::from_secs(1));
+        thread::sleep(step);
+    }
+}
+
+/// Answers `GET /` and `GET /status` with the status in a thread of its own.
+fn serve_status(address: &str, status: Arc<Mutex<Status>>) -> Result<Arc<Server>> {
+    let server = Server::http(address)
+        .map_err(|e| format!("Cannot serve the status at {0}: {1}", address, e))?;
+    let server = Arc::new(server);
+
+    let listener = server.clone();
+    thread::spawn(move || {
+        for request in listener.incoming_requests() {
+            let response = match request.url() {
+                "/" | "/status" => match serde_json::to_string_pretty(&*status.lock().unwrap()) {
+                    Ok(json) => Response::from_string(json)
+                        .with_header(Header::from_bytes("Content-Type", "application/json").expect("Valid header")),
+                    Err(e) => Response::from_string(e.to_string()).with_status_code(500),
+                },
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+            // The client may be gone already.
+            let _ = request.respond(response);
+        }
+    });
+    Ok(server)
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Utc};
+
+/// Limits downloaded items to those created within the range. Both bounds are inclusive.
+#[derive(Default, Clone, Copy)]
+pub struct DateRange {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: &DateTime<Utc>) -> bool {
+        self.since.is_none_or(|s| *date >= s) && self.until.is_none_or(|u| *date <= u)
+    }
+
+    /// Returns true if the date is older than the lower bound, so all the following
+    /// items of a newest-first feed are out of the range too.
+    pub fn is_passed(&self, date: &DateTime<Utc>) -> bool {
+        self.since.is_some_and(|s| *date < s)
+    }
+}
+
+/// Parses a range bound given either as a date (`2023-09-01`) or as an RFC 3339 timestamp.
+/// A date denotes the start of the day for the lower bound and the end of the day for the upper one.
+pub fn parse_bound(value: &str, is_upper: bool) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = if is_upper {
+            NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap()
+        } else {
+            NaiveTime::from_hms_opt(0, 0, 0).unwrap()
+        };
+        return Ok(Utc.from_utc_datetime(&date.and_time(time)));
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|d| d.with_timezone(&Utc))
+        .map_err(|_| format!("'{0}' is neither a date (YYYY-MM-DD) nor an RFC 3339 timestamp", value))
+}
+
+/// Formats the date as an ISO 8601 timestamp in UTC, e.g. `2024-05-03T09:00:00Z`, as used by the exports.
+pub fn format_timestamp(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
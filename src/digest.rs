@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use error_chain::error_chain;
+use serde::{Deserialize, Serialize};
+
+use crate::html;
+use crate::post::Post;
+use crate::smtp::{self, InlineImage, SmtpServer};
+use crate::thumbnail;
+
+error_chain! {
+    links {
+        Smtp(smtp::Error, smtp::ErrorKind);
+    }
+    foreign_links {
+        Io(std::io::Error);
+        Json(serde_json::Error);
+    }
+}
+
+/// Name of the file in the output folder remembering the posts sent already.
+const STATE_FILE_NAME: &str = ".digest-state.json";
+
+/// Longest side of the photos in the email, in pixels.
+const PHOTO_SIZE: u32 = 800;
+
+/// A post of the archive with the stored photos that show the children.
+pub struct DigestPost {
+    pub post: Post,
+    pub photos: Vec<PathBuf>,
+}
+
+/// Recipients of the digest and how to reach them.
+pub struct Envelope {
+    pub server: SmtpServer,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DigestState {
+    /// Ids of the posts sent in previous digests.
+    sent: BTreeSet<String>,
+}
+
+/// Emails the posts not sent in a previous digest. The first digest covers the posts since `first_since` only.
+/// Returns the number of sent posts.
+pub fn send(output_dir: &Path, posts: Vec<DigestPost>, first_since: DateTime<Utc>, envelope: &Envelope)
+    -> Result<usize> {
+    let state_path = output_dir.join(STATE_FILE_NAME);
+    let mut state = match std::fs::read_to_string(&state_path) {
+        Ok(json) => serde_json::from_str(&json)
+            .chain_err(|| format!("Invalid digest state {0}, delete it to start over", state_path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => DigestState {
+            sent: posts.iter().filter(|p| p.post.date < first_since).map(|p| p.post.id.clone()).collect(),
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut posts: Vec<DigestPost> = posts.into_iter().filter(|p| !state.sent.contains(&p.post.id)).collect();
+    if posts.is_empty() {
+        return Ok(0);
+    }
+    posts.sort_by_key(|p| std::cmp::Reverse(p.post.date));
+
+    let mut images = vec![];
+    let mut rendered = vec![];
+    for p in &posts {
+        let mut content_ids = vec![];
+        for path in &p.photos {
+            let jpeg = match std::fs::read(path) {
+                Ok(jpeg) => jpeg,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // A photo that can't be decoded is sent as it is.
+            let jpeg = thumbnail::resize_jpeg(&jpeg, PHOTO_SIZE).unwrap_or(jpeg);
+            let content_id = format!("photo{0}@famly-dl", images.len() + 1);
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            content_ids.push(content_id.clone());
+            images.push(InlineImage { content_id, file_name, jpeg });
+        }
+        rendered.push((&p.post, content_ids));
+    }
+
+    let subject = match posts.len() {
+        1 => format!("Famly: {0}", posts[0].post.get_title(true)),
+        n => format!("Famly: {0} new posts", n),
+    };
+    let message = smtp::compose(&envelope.from, &envelope.to, &subject, &html::render_digest(&rendered), &images);
+    smtp::send(&envelope.server, &envelope.from, &envelope.to, &message)?;
+
+    state.sent.extend(posts.iter().map(|p| p.post.id.clone()));
+    let part_path = state_path.with_extension("part");
+    std::fs::write(&part_path, serde_json::to_string(&state)?)?;
+    std::fs::rename(part_path, state_path)?;
+    Ok(posts.len())
+}
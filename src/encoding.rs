@@ -0,0 +1,4 @@
+/// Returns the bytes as lowercase hexadecimal digits, e.g. to print a SHA-256 digest.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,131 @@
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use error_chain::error_chain;
+use serde_json::json;
+
+use crate::date_range::format_timestamp;
+use crate::post::{Photo, Post};
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Csv(csv::Error);
+        Json(serde_json::Error);
+    }
+}
+
+/// Name of the folder in the output folder with the exports.
+pub const DIR_NAME: &str = "export";
+
+/// Writes `posts.jsonl` to the folder: a post per line with its photos, comments and attachments nested.
+pub fn write_jsonl(dir: &Path, posts: &[&Post]) -> Result<()> {
+    write_atomically(&dir.join("posts.jsonl"), |file| {
+        let mut writer = BufWriter::new(file);
+        for post in posts {
+            let photos: Vec<_> = post.photos.iter()
+                .map(|p| json!({
+                    "id": p.id,
+                    "date": format_timestamp(&p.date),
+                    "file_name": p.get_file_name(),
+                    "tags": p.tags,
+                }))
+                .collect();
+            let comments: Vec<_> = post.comments.iter()
+                .map(|c| json!({ "id": c.id, "date": format_timestamp(&c.date), "author": c.author, "text": c.text }))
+                .collect();
+            let attachments: Vec<_> = post.attachments.iter()
+                .map(|a| json!({ "id": a.id, "name": a.name, "url": a.url }))
+                .collect();
+
+            let line = json!({
+                "id": post.id,
+                "date": format_timestamp(&post.date),
+                "author": post.author,
+                "text": post.text,
+                "file_name": post.get_file_name(),
+                "photos": photos,
+                "comments": comments,
+                "attachments": attachments,
+            });
+            serde_json::to_writer(&mut writer, &line)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+/// Writes `posts.csv`, `photos.csv` and `comments.csv` to the folder. Photos list the ones of the posts first,
+/// then the tagged photos not shared in a post, which have no `post_id`. Tags are child ids separated by `;`.
+pub fn write_csv(dir: &Path, posts: &[&Post], tagged_photos: &[&Photo]) -> Result<()> {
+    write_atomically(&dir.join("posts.csv"), |file| {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(["id", "date", "author", "text", "file_name", "photos", "comments"])?;
+        for post in posts {
+            writer.write_record([
+                post.id.clone(),
+                format_timestamp(&post.date),
+                post.author.clone(),
+                post.text.clone(),
+                post.get_file_name(),
+                post.photos.len().to_string(),
+                post.comments.len().to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    })?;
+
+    write_atomically(&dir.join("photos.csv"), |file| {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(["id", "post_id", "date", "file_name", "tags"])?;
+        let post_photos = posts.iter().flat_map(|post| post.photos.iter().map(|p| (Some(&post.id), p)));
+        let mut seen_ids = std::collections::HashSet::new();
+        for (post_id, photo) in post_photos.chain(tagged_photos.iter().map(|p| (None, *p))) {
+            if !seen_ids.insert(&photo.id) {
+                continue;
+            }
+            writer.write_record([
+                photo.id.clone(),
+                post_id.cloned().unwrap_or_default(),
+                format_timestamp(&photo.date),
+                photo.get_file_name(),
+                photo.tags.join(";"),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    })?;
+
+    write_atomically(&dir.join("comments.csv"), |file| {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(["id", "post_id", "date", "author", "text"])?;
+        for post in posts {
+            for comment in &post.comments {
+                writer.write_record([
+                    comment.id.clone(),
+                    post.id.clone(),
+                    format_timestamp(&comment.date),
+                    comment.author.clone(),
+                    comment.text.clone(),
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+/// Writes the file next to its final path first, so that readers never see a partial file.
+fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+    where F: FnOnce(&mut std::fs::File) -> Result<()>
+{
+    // `posts.jsonl` and `posts.csv` must not share the temporary file.
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    let mut file = std::fs::File::create(&part_path)?;
+    write(&mut file)?;
+    std::fs::rename(part_path, path)?;
+    Ok(())
+}
@@ -1,11 +1,50 @@
 use std::fs;
+use std::io;
 use std::path::Path;
 
-pub fn create_dir(name: &str) -> std::io::Result<()> {
-    let path = Path::new(name);
-    if !std::path::Path::exists(&path) {
-        fs::create_dir(path)
+pub fn create_dir(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        fs::create_dir_all(path)
     } else {
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Makes `alias` point to the sibling folder `target`, replacing an outdated alias.
+/// Real folders and files with the alias name are never touched.
+pub fn create_alias(alias: &Path, target: &str) -> io::Result<()> {
+    if let Ok(meta) = fs::symlink_metadata(alias) {
+        if !meta.file_type().is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists and is not an alias", alias.display())));
+        }
+        if fs::read_link(alias)? == Path::new(target) {
+            return Ok(());
+        }
+        remove_alias(alias)?;
+    }
+
+    symlink_dir(target, alias)
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &str, alias: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, alias)
+}
+
+#[cfg(windows)]
+fn symlink_dir(target: &str, alias: &Path) -> io::Result<()> {
+    // Requires either admin rights or the developer mode.
+    std::os::windows::fs::symlink_dir(target, alias)
+}
+
+#[cfg(unix)]
+fn remove_alias(alias: &Path) -> io::Result<()> {
+    fs::remove_file(alias)
+}
+
+#[cfg(windows)]
+fn remove_alias(alias: &Path) -> io::Result<()> {
+    fs::remove_dir(alias)
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use error_chain::error_chain;
+use reqwest::Url;
+use serde_json::Value;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Json(serde_json::Error);
+        Base64(base64::DecodeError);
+    }
+}
+
+/// Famly API responses extracted from a HAR file saved by browser DevTools, in the order they were loaded.
+#[derive(Default)]
+pub struct HarResponses {
+    /// The list of children, if it was loaded.
+    pub child_infos: Option<String>,
+    pub feed_pages: Vec<String>,
+    /// Pages of tagged photos by child id.
+    pub tagged_photos_pages: HashMap<String, Vec<String>>,
+}
+
+pub fn read(path: &Path) -> Result<HarResponses> {
+    let content = std::fs::read_to_string(path)
+        .chain_err(|| format!("Cannot read the HAR file {0}", path.display()))?;
+    let har: Value = serde_json::from_str(&content)
+        .chain_err(|| format!("{0} is not a HAR file", path.display()))?;
+    let entries = har["log"]["entries"].as_array()
+        .ok_or_else(|| format!("No entries in the HAR file {0}", path.display()))?;
+
+    let mut responses = HarResponses::default();
+    for e in entries {
+        let url = match e["request"]["url"].as_str().and_then(|u| Url::parse(u).ok()) {
+            Some(url) => url,
+            None => continue,
+        };
+        if e["request"]["method"].as_str() != Some("GET") || e["response"]["status"].as_u64() != Some(200) {
+            continue;
+        }
+
+        let is_known = matches!(url.path(), "/api/v2/calendar/list" | "/api/feed/feed/feed" | "/api/v2/images/tagged");
+        if !is_known {
+            continue;
+        }
+
+        let body = match read_body(&e["response"]["content"])? {
+            Some(body) => body,
+            None => {
+                println!("The response of {0} is not saved in the HAR file, skipped", url);
+                continue;
+            },
+        };
+
+        match url.path() {
+            "/api/v2/calendar/list" => responses.child_infos = Some(body),
+            "/api/feed/feed/feed" => responses.feed_pages.push(body),
+            _ => {
+                let child_id = url.query_pairs()
+                    .find(|(k, _)| k == "childId")
+                    .map(|(_, v)| v.into_owned())
+                    .ok_or_else(|| format!("No childId in {0}", url))?;
+                responses.tagged_photos_pages.entry(child_id).or_default().push(body);
+            },
+        }
+    }
+
+    Ok(responses)
+}
+
+/// Returns the response body, browsers save binary or compressed bodies base64-encoded.
+fn read_body(content: &Value) -> Result<Option<String>> {
+    let text = match content["text"].as_str() {
+        Some(text) if !text.is_empty() => text,
+        _ => return Ok(None),
+    };
+
+    if content["encoding"].as_str() == Some("base64") {
+        let bytes = base64::decode(text)?;
+        let body = String::from_utf8(bytes).map_err(|_| "A response body is not valid UTF-8")?;
+        Ok(Some(body))
+    } else {
+        Ok(Some(text.to_string()))
+    }
+}
@@ -1,7 +1,8 @@
 use chrono::Datelike;
 
 use crate::child_info::ChildInfo;
-use crate::post::Post;
+use crate::post::{Post, PostSummary};
+use crate::search_index;
 
 pub fn render_post(post: &Post, child: &ChildInfo) -> String {
     let mut photos = String::new();
@@ -57,9 +58,103 @@ pub fn render_post(post: &Post, child: &ChildInfo) -> String {
         comments = comments)
 }
 
-pub fn render_index(posts: &Vec<Post>, has_tagged_photos: bool) -> String {
+pub fn render_index(posts: &[&PostSummary], has_tagged_photos: bool) -> String {
+    let posts_html = render_posts_table(posts, |p| format!(
+        r#"<a href="posts/{}">{}</a><br />"#,
+        p.file_name,
+        p.title));
+
+    let tagged_photos_html = if has_tagged_photos {
+        r#"<h3>Tagged photos</h3>
+    <a href="tagged_photos">Click to open</a>"#
+    } else {
+        ""
+    };
+
+    render_page(format!("{}\n    {}\n    {}", render_search_box(), posts_html, tagged_photos_html))
+}
+
+/// Renders the combined index of several children: links to every child's own archive
+/// and a single table of all posts, each linking into the archive of the first tagged child.
+pub fn render_family_index(posts: &[&PostSummary], children: &[&ChildInfo]) -> String {
+    let mut children_html = String::from("<h3>Children</h3>\n    <ul>");
+    for c in children {
+        children_html.push_str(format!(
+            r#"<li><a href="{0}/index.htm">{1}</a> ({2})</li>"#,
+            c.get_folder_name(),
+            c.full_name_with_institution,
+            c.institution).as_str());
+    }
+    children_html.push_str("</ul>");
+
+    let posts_html = render_posts_table(posts, |p| {
+        let tagged: Vec<&&ChildInfo> = children.iter()
+            .filter(|c| p.is_tagged(&c.id))
+            .collect();
+        match tagged.first() {
+            Some(first) => format!(
+                r#"<a href="{}/posts/{}">{}</a> <small class="text-muted">{}</small><br />"#,
+                first.get_folder_name(),
+                p.file_name,
+                p.title,
+                tagged.iter().map(|c| c.get_first_name()).collect::<Vec<_>>().join(", ")),
+            None => String::new(),
+        }
+    });
+
+    render_page(format!("{}\n    {}\n    {}", render_search_box(), children_html, posts_html))
+}
+
+/// Renders the search box of an index page, which searches the posts of `search.js` next to the page.
+/// It works offline, also when the page is opened from `file://`.
+fn render_search_box() -> String {
+    format!(r#"<input type="search" id="search" class="form-control" placeholder="Search posts and comments" />
+    <div id="search-results" class="mb-3"></div>
+    <script src="{0}"></script>
+    <script>
+    (function () {{
+        var input = document.getElementById('search');
+        var results = document.getElementById('search-results');
+        var posts = window.FAMLY_SEARCH_INDEX || [];
+        posts.forEach(function (p) {{
+            p.searched = [p.author, p.text].concat(p.comments).join('\n').toLowerCase();
+        }});
+        input.addEventListener('input', function () {{
+            var words = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+            results.textContent = '';
+            if (!words.length) {{
+                return;
+            }}
+            var found = posts.filter(function (p) {{
+                return words.every(function (w) {{ return p.searched.indexOf(w) >= 0; }});
+            }});
+            var summary = document.createElement('p');
+            summary.className = 'text-muted mt-2';
+            summary.textContent = found.length + (found.length == 1 ? ' post found' : ' posts found');
+            results.appendChild(summary);
+            found.slice(0, 100).forEach(function (p) {{
+                var item = document.createElement('div');
+                var link = document.createElement('a');
+                link.href = p.url;
+                link.textContent = p.title || p.date;
+                var details = document.createElement('small');
+                details.className = 'text-muted ms-2';
+                details.textContent = p.author + ', ' + p.date;
+                item.appendChild(link);
+                item.appendChild(details);
+                results.appendChild(item);
+            }});
+        }});
+    }})();
+    </script>"#, search_index::FILE_NAME)
+}
+
+/// Renders the table of posts grouped by month. Posts are expected to be ordered by date.
+fn render_posts_table<F>(posts: &[&PostSummary], render_link: F) -> String
+    where F: Fn(&PostSummary) -> String,
+{
     let mut posts_html = String::new();
-    if posts.len() > 0 {
+    if !posts.is_empty() {
         posts_html.push_str(r#"
     <h3>Posts</h3>
     <table class="table">
@@ -92,10 +187,7 @@ pub fn render_index(posts: &Vec<Post>, has_tagged_photos: bool) -> String {
                 prev_month = month;
             }
 
-            posts_html.push_str(format!(
-                r#"<a href="posts/{}">{}</a><br />"#,
-                p.get_file_name(),
-                p.get_title(false)).as_str());
+            posts_html.push_str(render_link(p).as_str());
         }
 
         // Close table.
@@ -104,13 +196,111 @@ pub fn render_index(posts: &Vec<Post>, has_tagged_photos: bool) -> String {
     </table>"#);
     }
 
-    let tagged_photos_html = if has_tagged_photos {
-        r#"<h3>Tagged photos</h3>
-    <a href="tagged_photos">Click to open</a>"#
-    } else {
-        ""
-    };
+    posts_html
+}
+
+/// Renders the email digest of posts, newest first. Photos are given as content ids of inline attachments.
+/// Emails get inline styles only, as mail clients ignore style sheets.
+pub fn render_digest(posts: &[(&Post, Vec<String>)]) -> String {
+    let mut posts_html = String::new();
+    for (post, content_ids) in posts {
+        let photos: String = content_ids.iter()
+            .map(|id| format!(r#"<img src="cid:{0}" style="max-width: 100%; margin: 4px 0;" /><br />"#, id))
+            .collect();
+        let comments: String = post.comments.iter()
+            .map(|c| format!(r#"<p style="background: #f1f1f1; padding: 6px; border-radius: 4px;">💬 <b>{0}</b><br />{1}</p>"#,
+                escape(&c.author), escape(&c.text).replace('\n', "<br />")))
+            .collect();
+
+        posts_html.push_str(&format!(r#"
+    <div style="border-bottom: 1px solid #ddd; padding: 12px 0;">
+        <p><b>{author}</b><br /><small style="color: #666;">{date}</small></p>
+        <p>{text}</p>
+        {photos}
+        {comments}
+    </div>"#,
+            author = escape(&post.author),
+            date = post.date.with_timezone(&chrono::Local).format("%d.%m.%Y %H:%M"),
+            text = escape(&post.text).replace('\n', "<br />"),
+            photos = photos,
+            comments = comments));
+    }
+
+    format!(r#"<!doctype html>
+<html>
+<head>
+    <meta charset="utf-8">
+</head>
+<body style="font-family: sans-serif; max-width: 800px;">{0}
+</body>
+</html>"#, posts_html)
+}
 
+/// Renders the search form and the posts found, each given with the folder of the child archive it's linked into.
+/// Photos are shown as thumbnails scaled by the server.
+pub fn render_search(query: &str, results: &[(&Post, String)], total: usize) -> String {
+    let mut results_html = String::new();
+    if !query.trim().is_empty() {
+        results_html.push_str(&match total {
+            0 => "<p>No posts found.</p>".to_string(),
+            n if n > results.len() => format!("<p>{0} posts found, showing the newest {1}.</p>", n, results.len()),
+            1 => "<p>1 post found.</p>".to_string(),
+            n => format!("<p>{0} posts found.</p>", n),
+        });
+    }
+
+    for (post, folder) in results {
+        let photos: String = post.photos.iter()
+            .map(|p| format!(
+                r#"<a href="/{0}/posts/photos/{1}"><img src="/{0}/posts/photos/{1}?thumbnail=160" class="img-thumbnail me-1 mb-1" /></a>"#,
+                folder, p.get_file_name()))
+            .collect();
+        let comments: String = post.comments.iter()
+            .map(|c| format!(r#"<div class="bg-light border p-2 mb-1 rounded-3">💬<b class="ms-1">{0}</b> {1}</div>"#,
+                escape(&c.author), escape(&c.text)))
+            .collect();
+
+        results_html.push_str(&format!(r#"
+    <div class="border-bottom py-3">
+        <a href="/{folder}/posts/{file_name}"><b>{title}</b></a>
+        <small class="text-muted ms-2">{author}, {date}</small>
+        <div style="white-space: pre-line;">{text}</div>
+        <div class="mt-2">{photos}</div>
+        {comments}
+    </div>"#,
+            folder = folder,
+            file_name = post.get_file_name(),
+            title = escape(&post.get_title(true)),
+            author = escape(&post.author),
+            date = post.date.with_timezone(&chrono::Local).format("%d.%m.%Y %H:%M"),
+            text = escape(&post.text),
+            photos = photos,
+            comments = comments));
+    }
+
+    render_page(format!(r#"<form action="/search" class="d-flex mb-3">
+        <input type="search" name="q" value="{0}" class="form-control me-2" placeholder="Search posts and comments" autofocus />
+        <button type="submit" class="btn btn-primary">Search</button>
+    </form>
+    <a href="/">Archive</a>
+    {1}"#, escape(query), results_html))
+}
+
+/// Renders the list of files in a folder of the archive without an index page. Folder names end with a slash.
+pub fn render_folder(path: &str, names: &[String]) -> String {
+    let links: String = names.iter()
+        .map(|n| format!(r#"<li><a href="{0}">{1}</a></li>"#, urlencoding::encode(n).replace("%2F", "/"), escape(n)))
+        .collect();
+    render_page(format!(r#"<h3>{0}</h3>
+    <a href="/search">Search</a>
+    <ul>{1}</ul>"#, escape(path), links))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn render_page(body: String) -> String {
     format!(
         r#"<!doctype html>
 <html>
@@ -120,10 +310,8 @@ pub fn render_index(posts: &Vec<Post>, has_tagged_photos: bool) -> String {
         integrity="sha384-gH2yIJqKdNHPEq0n4Mqa/HGKIhSkIHeL5AyhkYV8i59U5AR6csBvApHHNl/vI1Bx" crossorigin="anonymous">
 </head>
 <body class="container py-3" style="max-width: 1000px;">
-    {posts}
-    {tagged_photos}
+    {body}
 </body>
 </html>"#,
-        posts = posts_html,
-        tagged_photos = tagged_photos_html)
-}
\ No newline at end of file
+        body = body)
+}
@@ -1,21 +1,54 @@
-use reqwest::blocking::Client;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use reqwest::header;
+use reqwest::StatusCode;
 use reqwest::header::HeaderValue;
+use std::collections::HashSet;
 use std::io::Read;
 use error_chain::error_chain;
 use urlencoding::encode;
 
+use crate::date_range::DateRange;
+use crate::page::{Identified, Page};
+
 error_chain! {
     foreign_links {
         Io(std::io::Error);
         HttpRequest(reqwest::Error);
     }
+
+    errors {
+        InvalidToken {
+            description("invalid access token")
+            display("The access token contains characters that are not allowed, check that it is copied correctly")
+        }
+        Unauthorized(status: u16) {
+            description("access token is rejected")
+            display("Famly rejected the access token (HTTP {}). It has most likely expired: \
+                log in to Famly in a browser and copy a new token, or configure the login to renew it automatically", status)
+        }
+        ServerError(status: u16) {
+            description("Famly server error")
+            display("Famly server failed to respond (HTTP {}), try again later", status)
+        }
+        Unreachable(reason: String) {
+            description("Famly is unreachable")
+            display("Cannot reach Famly, check the internet connection: {}", reason)
+        }
+        ImageUnavailable(status: u16) {
+            description("photo is unavailable")
+            display("The photo link has expired or is invalid (HTTP {}), run again to get fresh links", status)
+        }
+        LoginFailed(reason: String) {
+            description("login failed")
+            display("Failed to log in to Famly: {}", reason)
+        }
+    }
 }
 
 lazy_static::lazy_static! {
     static ref IMG_CLIENT: Client = {
         let mut headers = header::HeaderMap::new();
-        headers.insert(header::HOST, HeaderValue::from_static("img.famly.de"));
         headers.insert(header::USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:102.0) Gecko/20100101 Firefox/102.0"));
         headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
         
@@ -26,14 +59,21 @@ lazy_static::lazy_static! {
     };
 }
 
-pub fn create_web_client(access_token: String) -> Result<Client> {
-    let mut access_token_header_val = HeaderValue::from_str(access_token.as_str()).unwrap();
+/// The API of the German Famly instance.
+pub const DEFAULT_API_URL: &str = "https://app.famly.de";
+
+/// How many times a request is repeated when Famly asks to slow down.
+const MAX_RETRIES: u32 = 3;
+
+pub fn create_web_client(access_token: String, api_url: &str) -> Result<Client> {
+    let mut access_token_header_val = HeaderValue::from_str(access_token.trim())
+        .map_err(|_| ErrorKind::InvalidToken)?;
     access_token_header_val.set_sensitive(true);
 
     let mut headers = header::HeaderMap::new();
-    headers.insert(header::HOST, HeaderValue::from_static("app.famly.de"));
     headers.insert(header::USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:102.0) Gecko/20100101 Firefox/102.0"));
-    headers.insert(header::REFERER, HeaderValue::from_static("https://app.famly.de/"));
+    headers.insert(header::REFERER, HeaderValue::from_str(&format!("{}/", api_url))
+        .map_err(|_| format!("Invalid API URL '{}'", api_url))?);
     headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
     headers.insert("x-famly-accesstoken", access_token_header_val);
     headers.insert("x-famly-installationid", HeaderValue::from_static("297e6a1d-d070-4e54-b6a4-3a73a325ccc1"));
@@ -48,84 +88,272 @@ pub fn create_web_client(access_token: String) -> Result<Client> {
     Ok(client)
 }
 
-pub fn fetch_child_infos(client: &Client) -> Result<String> {
+pub fn fetch_child_infos(client: &Client, api_url: &str) -> Result<String> {
     let mut body = String::new();
-    client
-        .get("https://app.famly.de/api/v2/calendar/list")
-        .send()?
-        .error_for_status()?
+    send(client.get(format!("{}/api/v2/calendar/list", api_url)))?
         .read_to_string(&mut body)?;
     Ok(body)
 }
 
-pub fn fetch_feed(client: &Client, older_than: &Option<String>) -> Result<String> {
-    let mut url = String::from("https://app.famly.de/api/feed/feed/feed?limit=100");
+pub fn fetch_feed(client: &Client, api_url: &str, older_than: &Option<String>) -> Result<String> {
+    let mut url = format!("{}/api/feed/feed/feed?limit=100", api_url);
     if let Some(date) = older_than {
         url.push_str("&olderThan=");
         url.push_str(encode(date).into_owned().as_str());
     }
 
-    let body = client
-        .get(url)
-        .send()?
-        .error_for_status()?
+    let body = send(client.get(url))?
         .text()?;
     Ok(body)
 }
 
-pub fn fetch_tagged_photos(client: &Client, child_id: &String, older_than: &Option<String>) -> Result<String> {
-    let mut url = format!("https://app.famly.de/api/v2/images/tagged?childId={}&limit=100", child_id);
+pub fn fetch_tagged_photos(client: &Client, api_url: &str, child_id: &str, older_than: &Option<String>) -> Result<String> {
+    let mut url = format!("{}/api/v2/images/tagged?childId={}&limit=100", api_url, child_id);
     if let Some(date) = older_than {
         url.push_str("&olderThan=");
         url.push_str(encode(date).into_owned().as_str());
     }
 
-    let body = client
-        .get(url)
-        .send()?
-        .error_for_status()?
+    let body = send(client.get(url))?
         .text()?;
     Ok(body)
 }
 
-/// Fetches all items available through paginated API by continuously calling the predicate
-/// and passing the date of the last item from the previous call.
-pub fn fetch_till_exhausted<T, P>(load_next_batch: P) -> Result<Vec<T>>
+/// Logs in with the account credentials and returns a new access token.
+pub fn log_in(api_url: &str, email: &str, password: &str) -> Result<String> {
+    let query = r#"mutation Authenticate($email: EmailAddress!, $password: Password!, $deviceId: DeviceId) {
+  me {
+    authenticateWithPassword(email: $email, password: $password, deviceId: $deviceId) {
+      __typename
+      ... on AuthenticationSucceeded { accessToken }
+      ... on AuthenticationFailed { errorDetails }
+    }
+  }
+}"#;
+    let request = serde_json::json!({
+        "operationName": "Authenticate",
+        "query": query,
+        "variables": {
+            "email": email,
+            "password": password,
+            "deviceId": "297e6a1d-d070-4e54-b6a4-3a73a325ccc1",
+        },
+    });
+
+    let client = Client::builder().build()?;
+    let body = send(client
+        .post(format!("{}/graphql?Authenticate", api_url))
+        .header(header::USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:102.0) Gecko/20100101 Firefox/102.0")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(request.to_string()))?
+        .text()?;
+
+    let response: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| ErrorKind::LoginFailed(format!("unexpected response: {}", e)))?;
+    let result = &response["data"]["me"]["authenticateWithPassword"];
+    match result["__typename"].as_str() {
+        Some("AuthenticationSucceeded") => result["accessToken"].as_str()
+            .map(|t| t.to_string())
+            .ok_or_else(|| ErrorKind::LoginFailed("no access token in the response".to_string()).into()),
+        Some("AuthenticationFailed") => Err(ErrorKind::LoginFailed(
+            result["errorDetails"].as_str().unwrap_or("wrong email or password").to_string()).into()),
+        Some(other) => Err(ErrorKind::LoginFailed(
+            format!("{} is not supported, log in through a browser", other)).into()),
+        None => Err(ErrorKind::LoginFailed(
+            response["errors"][0]["message"].as_str().unwrap_or("unexpected response").to_string()).into()),
+    }
+}
+
+/// Sends the request and turns failures into errors telling apart token problems,
+/// network problems and server problems.
+fn send(request: RequestBuilder) -> Result<Response> {
+    let response = send_with_retries(request)?;
+
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(ErrorKind::Unauthorized(status.as_u16()).into());
+    }
+    if status.is_server_error() {
+        return Err(ErrorKind::ServerError(status.as_u16()).into());
+    }
+
+    Ok(response.error_for_status()?)
+}
+
+/// Sends the request, repeating it after a pause if Famly responds with "Too Many Requests".
+fn send_with_retries(mut request: RequestBuilder) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        let retry = request.try_clone();
+        let response = request.send().map_err(|e| -> Error {
+            if e.is_connect() || e.is_timeout() {
+                ErrorKind::Unreachable(e.to_string()).into()
+            } else {
+                e.into()
+            }
+        })?;
+
+        attempt += 1;
+        match retry {
+            Some(retry) if response.status() == StatusCode::TOO_MANY_REQUESTS && attempt <= MAX_RETRIES => {
+                let delay = response.headers()
+                    .get(header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(5 * attempt as u64);
+                println!("Famly asks to slow down, retrying in {} seconds...", delay);
+                std::thread::sleep(std::time::Duration::from_secs(delay.min(60)));
+                request = retry;
+            },
+            _ => return Ok(response),
+        }
+    }
+}
+
+/// Lazily loads pages of a paginated API by continuously calling the predicate
+/// and passing the date of the last item from the previous call, see `paginate`.
+pub struct Pages<'a, T, P>
     where
-        P: Fn(Option<String>) -> Result<(Vec<T>, Option<String>)>,
+        P: FnMut(Option<String>) -> Result<Page<T>>,
 {
-    let mut items = vec![];
+    load_next_batch: P,
+    range: &'a DateRange,
+    older_than: Option<String>,
+    /// Ids of items that may be returned once again.
+    seen_ids: HashSet<String>,
+    calls: u16,
+    exhausted: bool,
+}
 
-    let mut i = 0_u16;
-    let mut older_than = None;
-    loop {
-        i += 1;
-        if i % 5 == 0 {
-            println!("{} API calls done...", i);
+/// Returns an iterator over pages of items available through paginated API. A page is loaded only
+/// when requested, so items can be processed as they arrive and loading can stop at any moment.
+/// Starts right after the upper bound of the range and stops once items get older than its lower bound,
+/// items outside of the range still have to be filtered out by the caller.
+///
+/// Items sharing the date with the last item of a page would be skipped, if only items strictly older than
+/// that date were requested. So consecutive pages overlap a bit and items seen on the previous page are dropped.
+pub fn paginate<T, P>(range: &DateRange, load_next_batch: P) -> Pages<'_, T, P>
+    where
+        P: FnMut(Option<String>) -> Result<Page<T>>,
+{
+    Pages {
+        load_next_batch,
+        range,
+        older_than: range.until.map(|d| d.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        seen_ids: HashSet::new(),
+        calls: 0,
+        exhausted: false,
+    }
+}
+
+impl<T, P> Iterator for Pages<'_, T, P>
+    where
+        T: Identified,
+        P: FnMut(Option<String>) -> Result<Page<T>>,
+{
+    type Item = Result<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exhausted {
+            return None;
         }
 
-        let (batch, last_item_date) = load_next_batch(older_than)?;
+        self.calls += 1;
+        if self.calls.is_multiple_of(5) {
+            println!("{} API calls done...", self.calls);
+        }
 
-        items.extend(batch);
+        let older_than = self.older_than.take();
+        let page = match (self.load_next_batch)(older_than.clone()) {
+            Ok(page) => page,
+            Err(e) => {
+                // Retrying the same page would most likely fail again.
+                self.exhausted = true;
+                return Some(Err(e));
+            },
+        };
 
-        if last_item_date.is_none() {
-            // No older items are available.
-            break;
+        let items = page.items.into_iter()
+            .filter(|i| !self.seen_ids.contains(i.id()))
+            .collect();
+        let has_new_ids = page.ids.iter().any(|id| !self.seen_ids.contains(id));
+
+        let last_item_date = match page.last_item_date {
+            Some(date) if !is_before_range(&date, self.range) => date,
+            // No older items are available or needed.
+            _ => {
+                self.exhausted = true;
+                return Some(Ok(items));
+            },
+        };
+
+        let next_older_than = if has_new_ids {
+            self.seen_ids = page.ids.into_iter().collect();
+            overlap(&last_item_date, &older_than)
         } else {
-            older_than = last_item_date;
+            // The whole page shares the date with the end of the previous one,
+            // the only way forward is to skip the rest of the items with this date.
+            println!("Too many items dated {}, some of them may be skipped", last_item_date);
+            self.seen_ids.extend(page.ids);
+            last_item_date
+        };
+
+        if !is_advancing(&next_older_than, &older_than) {
+            self.exhausted = true;
+            return Some(Err(format!(
+                "Pagination doesn't advance: items older than {} were requested, but got items up to {}",
+                older_than.unwrap_or_default(), next_older_than).into()));
         }
+
+        self.older_than = Some(next_older_than);
+        Some(Ok(items))
+    }
+}
+
+/// Returns the date slightly after the given one, so items with exactly that date are loaded again,
+/// but still before the previous cursor, so pagination advances.
+fn overlap(date: &str, previous: &Option<String>) -> String {
+    let date = match DateTime::parse_from_rfc3339(date) {
+        Ok(d) => d,
+        Err(_) => return date.to_string(),
+    };
+
+    let mut next = date + Duration::seconds(1);
+    if let Some(Ok(previous)) = previous.as_deref().map(DateTime::parse_from_rfc3339) {
+        next = next.min(previous - Duration::milliseconds(1));
+    }
+    next.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Returns true if the next cursor is older than the previous one or if either can't be compared.
+fn is_advancing(next: &str, previous: &Option<String>) -> bool {
+    let previous = match previous {
+        Some(p) => p,
+        None => return true,
+    };
+
+    match (DateTime::parse_from_rfc3339(next), DateTime::parse_from_rfc3339(previous)) {
+        (Ok(n), Ok(p)) => n < p,
+        _ => next != previous,
     }
+}
 
-    Ok(items)
+fn is_before_range(date: &str, range: &DateRange) -> bool {
+    DateTime::parse_from_rfc3339(date)
+        .map(|d| range.is_passed(&d.with_timezone(&Utc)))
+        .unwrap_or(false)
 }
 
-pub fn download_image<W: ?Sized>(url: &String, writer: &mut W) -> Result<()>
-    where W: std::io::Write,
+pub fn download_image<W>(url: &str, writer: &mut W) -> Result<()>
+    where W: std::io::Write + ?Sized,
 {
-    let mut r = IMG_CLIENT
-        .get(url)
-        .send()?
-        .error_for_status()?;
+    let mut r = send_with_retries(IMG_CLIENT.get(url))?;
+
+    let status = r.status();
+    if status == StatusCode::FORBIDDEN || status == StatusCode::GONE || status == StatusCode::NOT_FOUND {
+        return Err(ErrorKind::ImageUnavailable(status.as_u16()).into());
+    }
+    r = r.error_for_status()?;
 
     r.copy_to(writer)?;
 
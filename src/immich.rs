@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use error_chain::error_chain;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::hex;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        HttpRequest(reqwest::Error);
+        Json(serde_json::Error);
+    }
+}
+
+/// Name of the file in the output folder remembering what was exported.
+const STATE_FILE_NAME: &str = ".immich-state.json";
+
+/// Identifies the uploads of famly-dl among the assets of the Immich user.
+const DEVICE_ID: &str = "famly-dl";
+
+/// Client of the Immich API, authenticated with an API key.
+pub struct ImmichClient {
+    client: Client,
+    /// URL of the server, ending with `/`.
+    url: Url,
+    api_key: String,
+}
+
+impl ImmichClient {
+    /// `url` is the address of the Immich web interface, e.g. `http://localhost:2283`.
+    pub fn new(url: &str, api_key: &str) -> Result<ImmichClient> {
+        let url = Url::parse(&format!("{}/", url.trim_end_matches('/')))
+            .map_err(|e| format!("Invalid Immich URL '{0}': {1}", url, e))?;
+        Ok(ImmichClient { client: Client::new(), url, api_key: api_key.to_string() })
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let url = self.url.join(path).map_err(|e| format!("Invalid Immich path '{0}': {1}", path, e))?;
+        Ok(self.client.request(method, url).header("x-api-key", &self.api_key).header("Accept", "application/json"))
+    }
+
+    fn send_json(&self, method: Method, path: &str, body: Value) -> Result<Response> {
+        let response = self.request(method, path)?
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()?;
+        check(response)
+    }
+
+    /// Returns ids of the user's albums by their names.
+    pub fn albums(&self) -> Result<HashMap<String, String>> {
+        let albums = parse(check(self.request(Method::GET, "api/albums")?.send()?)?)?;
+        Ok(albums.as_array().ok_or("No albums array in the Immich response")?.iter()
+            .filter_map(|a| Some((a["albumName"].as_str()?.to_string(), a["id"].as_str()?.to_string())))
+            .collect())
+    }
+
+    /// Creates an empty album and returns its id.
+    pub fn create_album(&self, name: &str) -> Result<String> {
+        let response = self.send_json(Method::POST, "api/albums", json!({ "albumName": name }))?;
+        parse_id(parse(response)?)
+    }
+
+    /// Uploads the image and returns the id of its asset. Immich recognizes images it already has
+    /// and returns the existing asset.
+    pub fn upload(&self, device_asset_id: &str, date: &DateTime<Utc>, file_name: &str, content: &[u8])
+        -> Result<String> {
+        let date = date.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let boundary = format!("famly-dl-{}", hex(&Sha256::digest(content)));
+        let mut body = vec![];
+        for (name, value) in [
+            ("deviceAssetId", device_asset_id),
+            ("deviceId", DEVICE_ID),
+            ("fileCreatedAt", &date),
+            ("fileModifiedAt", &date),
+        ] {
+            body.extend(format!("--{0}\r\nContent-Disposition: form-data; name=\"{1}\"\r\n\r\n{2}\r\n",
+                boundary, name, value).into_bytes());
+        }
+        body.extend(format!("--{0}\r\nContent-Disposition: form-data; name=\"assetData\"; filename=\"{1}\"\r\n\
+            Content-Type: image/jpeg\r\n\r\n", boundary, file_name).into_bytes());
+        body.extend_from_slice(content);
+        body.extend(format!("\r\n--{0}--\r\n", boundary).into_bytes());
+
+        let response = self.request(Method::POST, "api/assets")?
+            .header("Content-Type", format!("multipart/form-data; boundary={0}", boundary))
+            .body(body)
+            .send()?;
+        parse_id(parse(check(response)?)?)
+    }
+
+    pub fn set_description(&self, asset_id: &str, description: &str) -> Result<()> {
+        self.send_json(Method::PUT, &format!("api/assets/{0}", asset_id), json!({ "description": description }))?;
+        Ok(())
+    }
+
+    /// Adds the assets to the album, assets already in it are left alone.
+    pub fn add_to_album(&self, album_id: &str, asset_ids: &[&str]) -> Result<()> {
+        self.send_json(Method::PUT, &format!("api/albums/{0}/assets", album_id), json!({ "ids": asset_ids }))?;
+        Ok(())
+    }
+}
+
+fn check(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(format!("Immich rejected the API key (HTTP {0})", status.as_u16()).into());
+    }
+
+    let url = response.url().path().to_string();
+    let body: Value = response.text().ok().and_then(|t| serde_json::from_str(&t).ok()).unwrap_or_default();
+    match body["message"].as_str() {
+        Some(message) => Err(format!("Immich request {0} failed (HTTP {1}): {2}", url, status.as_u16(), message).into()),
+        None => Err(format!("Immich request {0} failed (HTTP {1})", url, status.as_u16()).into()),
+    }
+}
+
+fn parse(response: Response) -> Result<Value> {
+    Ok(serde_json::from_str(&response.text()?)?)
+}
+
+fn parse_id(json: Value) -> Result<String> {
+    Ok(json["id"].as_str().ok_or("No id in the Immich response")?.to_string())
+}
+
+/// A photo of the archive to export.
+pub struct ExportedPhoto {
+    /// Famly id of the photo.
+    pub id: String,
+    pub date: DateTime<Utc>,
+    /// The image file in the archive.
+    pub path: PathBuf,
+    /// Text of the post the photo belongs to.
+    pub description: Option<String>,
+    pub album: String,
+}
+
+/// Photos exported to a server so far, to export only new ones.
+#[derive(Serialize, Deserialize, Default)]
+struct ExportState {
+    /// The state is discarded when the server changes.
+    server: String,
+    /// Asset ids by photo ids.
+    assets: BTreeMap<String, String>,
+    /// Ids of photos added to the album by album names.
+    albums: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Uploads the photos not exported before with their dates and descriptions, and adds them to their albums,
+/// which are created as needed. Returns the number of uploaded photos.
+pub fn export(client: &ImmichClient, output_dir: &Path, photos: &[ExportedPhoto]) -> Result<usize> {
+    let state_path = output_dir.join(STATE_FILE_NAME);
+    let mut state = read_state(&state_path, client.url.as_str())?;
+    let mut album_ids = client.albums()?;
+
+    let mut uploaded = 0;
+    let mut exported = 0;
+    for photo in photos {
+        if state.albums.get(&photo.album).map(|ids| ids.contains(&photo.id)).unwrap_or(false) {
+            continue;
+        }
+
+        let asset_id = match state.assets.get(&photo.id) {
+            Some(asset_id) => asset_id.clone(),
+            None => {
+                let content = match std::fs::read(&photo.path) {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        println!("{0} is missing, it cannot be exported", photo.path.display());
+                        continue;
+                    },
+                    Err(e) => return Err(e.into()),
+                };
+                let file_name = photo.path.file_name().unwrap_or_default().to_string_lossy();
+                let asset_id = client.upload(&format!("famly-{0}", photo.id), &photo.date, &file_name, &content)?;
+                if let Some(description) = &photo.description {
+                    client.set_description(&asset_id, description)?;
+                }
+                uploaded += 1;
+                if uploaded % 50 == 0 {
+                    println!("{} photos uploaded...", uploaded);
+                }
+                asset_id
+            },
+        };
+
+        let album_id = match album_ids.get(&photo.album) {
+            Some(album_id) => album_id.clone(),
+            None => {
+                println!("Creating the album {0}", photo.album);
+                let album_id = client.create_album(&photo.album)?;
+                album_ids.insert(photo.album.clone(), album_id.clone());
+                album_id
+            },
+        };
+        client.add_to_album(&album_id, &[&asset_id])?;
+
+        state.assets.insert(photo.id.clone(), asset_id);
+        state.albums.entry(photo.album.clone()).or_default().insert(photo.id.clone());
+        exported += 1;
+        // Saved as it goes, so an interrupted export resumes about where it stopped. Immich recognizes
+        // images uploaded again.
+        if exported % 50 == 0 {
+            write_state(&state_path, &state)?;
+        }
+    }
+
+    write_state(&state_path, &state)?;
+    Ok(uploaded)
+}
+
+fn read_state(path: &Path, server: &str) -> Result<ExportState> {
+    let state: ExportState = match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .chain_err(|| format!("Invalid Immich export state {0}, delete it to export everything again", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ExportState::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if state.server != server {
+        return Ok(ExportState { server: server.to_string(), ..ExportState::default() });
+    }
+    Ok(state)
+}
+
+fn write_state(path: &Path, state: &ExportState) -> Result<()> {
+    let part_path = path.with_extension("part");
+    std::fs::write(&part_path, serde_json::to_string(state)?)?;
+    std::fs::rename(part_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! Downloads posts and photos of children from Famly.
+//!
+//! [`FamlyClient`] gives access to the children, posts and tagged photos of an account through [`FeedSource`],
+//! [`run_cli`] runs the `famly-dl` command line application built on top of it.
+
+mod app;
+pub mod child_info;
+mod cli;
+pub mod client;
+mod config;
+mod console;
+mod daemon;
+mod date_range;
+mod digest;
+mod encoding;
+mod export;
+mod file_system;
+mod har;
+mod html;
+mod http;
+mod immich;
+mod json;
+mod notify;
+pub mod page;
+pub mod post;
+mod raw;
+mod s3;
+mod schedule;
+mod search_index;
+mod serve;
+mod shutdown;
+mod sidecar;
+mod smtp;
+pub mod source;
+mod sqlite;
+mod storage;
+mod thumbnail;
+mod token;
+mod upload;
+mod webdav;
+mod xml;
+
+pub use child_info::ChildInfo;
+pub use client::FamlyClient;
+pub use date_range::DateRange;
+pub use post::{Photo, Post};
+pub use source::{FeedSource, FixtureSource};
+
+/// Runs `famly-dl` with the arguments of the process, exiting with an error code if it fails.
+pub fn run_cli() {
+    use clap::Parser;
+
+    let args = cli::Args::parse();
+    if let Err(e) = app::run(&args) {
+        eprintln!("Error: {}", e);
+        for cause in e.iter().skip(1) {
+            eprintln!("Caused by: {}", cause);
+        }
+        std::process::exit(1);
+    }
+}
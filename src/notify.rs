@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use error_chain::error_chain;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_json::json;
+
+use crate::post::Post;
+
+error_chain! {
+    foreign_links {
+        HttpRequest(reqwest::Error);
+    }
+}
+
+/// How many posts a notification lists, the rest are only counted.
+const MAX_LISTED_POSTS: usize = 10;
+
+/// What notifications tell about a new post.
+pub struct NewPost {
+    pub date: DateTime<Utc>,
+    pub author: String,
+    pub title: String,
+    pub photo_count: usize,
+    /// Thumbnail of the first photo (valid only for some time).
+    pub thumbnail_url: Option<String>,
+}
+
+impl NewPost {
+    pub fn new(post: &Post) -> NewPost {
+        NewPost {
+            date: post.date,
+            author: post.author.clone(),
+            title: post.get_title(false),
+            photo_count: post.photos.len(),
+            thumbnail_url: post.photos.first().map(|p| p.thumbnail_url.clone()),
+        }
+    }
+
+    /// Returns a line like `03.05. Jane Doe: Trip to the zoo (2 photos)`.
+    fn describe(&self) -> String {
+        let photos = match self.photo_count {
+            0 => String::new(),
+            1 => " (1 photo)".to_string(),
+            n => format!(" ({0} photos)", n),
+        };
+        format!("{0} {1}: {2}{3}", self.date.format("%d.%m."), self.author, self.title, photos)
+    }
+}
+
+/// An endpoint notifications about new posts are sent to.
+pub enum Notifier {
+    /// Receives the new posts as JSON.
+    Webhook { url: String },
+    /// URL of an ntfy topic, e.g. `https://ntfy.sh/famly-posts`, optionally with an access token.
+    Ntfy { url: String, token: Option<String> },
+    /// URL of a Gotify server and the token of its application.
+    Gotify { url: String, token: String },
+}
+
+impl Notifier {
+    /// Sends a summary of the posts.
+    pub fn send(&self, posts: &[NewPost]) -> Result<()> {
+        let title = match posts.len() {
+            1 => "1 new Famly post".to_string(),
+            n => format!("{0} new Famly posts", n),
+        };
+        let mut lines: Vec<String> = posts.iter().take(MAX_LISTED_POSTS).map(NewPost::describe).collect();
+        if posts.len() > MAX_LISTED_POSTS {
+            lines.push(format!("and {0} more", posts.len() - MAX_LISTED_POSTS));
+        }
+        let message = lines.join("\n");
+        let thumbnail_url = posts.iter().find_map(|p| p.thumbnail_url.as_deref());
+
+        let client = Client::new();
+        let request = match self {
+            Notifier::Webhook { url } => {
+                let posts: Vec<_> = posts.iter().map(|p| json!({
+                    "date": p.date.to_rfc3339(),
+                    "author": p.author,
+                    "title": p.title,
+                    "photos": p.photo_count,
+                    "thumbnail_url": p.thumbnail_url,
+                })).collect();
+                json_request(client.post(url), json!({ "title": title, "message": message, "posts": posts }))
+            },
+            Notifier::Ntfy { url, token } => {
+                let mut request = client.post(url).header("Title", &title).header("Tags", "camera").body(message);
+                if let Some(thumbnail_url) = thumbnail_url {
+                    request = request.header("Attach", thumbnail_url);
+                }
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request
+            },
+            Notifier::Gotify { url, token } => {
+                let mut extras = json!({});
+                if let Some(thumbnail_url) = thumbnail_url {
+                    extras["client::notification"] = json!({ "bigImageUrl": thumbnail_url });
+                }
+                let body = json!({ "title": title, "message": message, "priority": 5, "extras": extras });
+                json_request(client.post(format!("{0}/message", url.trim_end_matches('/'))), body)
+                    .header("X-Gotify-Key", token)
+            },
+        };
+
+        let response = request.send()?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {0}", response.status().as_u16()).into());
+        }
+        Ok(())
+    }
+}
+
+fn json_request(request: RequestBuilder, body: serde_json::Value) -> RequestBuilder {
+    request.header("Content-Type", "application/json").body(body.to_string())
+}
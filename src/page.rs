@@ -0,0 +1,24 @@
+use std::collections::HashSet;
+
+/// A single page of items loaded from a paginated API.
+pub struct Page<T> {
+    /// Parsed items, without the ones that are not of interest.
+    pub items: Vec<T>,
+    /// Ids of all items on the page, including the skipped ones.
+    pub ids: Vec<String>,
+    /// `None` if there were no items on the page, otherwise the date of the last item
+    /// to fetch subsequent items with.
+    pub last_item_date: Option<String>,
+}
+
+/// An item with an id that stays the same across API calls.
+pub trait Identified {
+    fn id(&self) -> &str;
+}
+
+/// Drops items returned by previous pages already, e.g. by a repeated request.
+pub fn dedupe<T: Identified>(items: Vec<T>, seen_ids: &mut HashSet<String>) -> Vec<T> {
+    items.into_iter()
+        .filter(|i| seen_ids.insert(i.id().to_string()))
+        .collect()
+}
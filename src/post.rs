@@ -3,6 +3,7 @@ use error_chain::error_chain;
 use serde_json::Value;
 
 use crate::json::*;
+use crate::page::{Identified, Page};
 
 error_chain! {
     foreign_links {
@@ -10,12 +11,18 @@ error_chain! {
     }
 }
 
+/// Longest side of thumbnails in pixels.
+const THUMBNAIL_SIZE: u64 = 400;
+
 pub struct Photo {
     pub id: String,
     pub date: DateTime<Utc>,
     /// URL of the *full* size image (valid only for some time).
     pub url: String,
-    tags: Vec<String>,
+    /// URL of the image scaled down to fit `THUMBNAIL_SIZE` (valid only for some time).
+    pub thumbnail_url: String,
+    /// Ids of the tagged children.
+    pub tags: Vec<String>,
 }
 
 impl Photo {
@@ -31,21 +38,19 @@ impl Photo {
         format!("{}_{}.jpg", date, short_id)
     }
 
-    /// Converts the raw JSON string to a tuple of:
-    /// * collection of photos
-    /// * an option value: `None` if there were no items in the json, otherwise `Some` with
-    /// the *last_item_date* string for fetching of subsequent items.
-    pub fn from_json_array(json: String) -> Result<(Vec<Photo>, Option<String>)> {
+    /// Converts the raw JSON string to a page of photos.
+    pub fn from_json_array(json: String) -> Result<Page<Photo>> {
         let parsed_json: Value = serde_json::from_str(&json)?;
         let items = parsed_json.as_array().ok_or("No photos array in json")?;
 
-        let photos = items.iter()
+        let photos: Vec<Photo> = items.iter()
             .map(|i| i.try_into().expect("Failed to deserialize a photo json"))
             .collect();
+        let ids = photos.iter().map(|p| p.id.clone()).collect();
     
         let last_item_date = items.last().map(|x| x["createdAt"].as_str().unwrap().to_string());
     
-        Ok((photos, last_item_date))
+        Ok(Page { items: photos, ids, last_item_date })
     }
 }
 
@@ -61,13 +66,13 @@ impl TryFrom<&Value> for Photo {
                 .map_err(|e| format!("Failed to parse '{0}' as date: {1}", date_str, e))?
         } else {
             // V2 version of API.
-            parse_date(&json, "createdAt")?
+            parse_date(json, "createdAt")?
         };
 
-        let prefix = parse_string(&json, "prefix")?;
-        let key = parse_string(&json, "key")?;
-        let height = parse_int(&json, "height")?;
-        let width = parse_int(&json, "width")?;
+        let prefix = parse_string(json, "prefix")?;
+        let key = parse_string(json, "key")?;
+        let height = parse_int(json, "height")?;
+        let width = parse_int(json, "width")?;
 
         let untyped_tags = json["tags"].as_array().ok_or("No tags array in image json")?;
         let tags = untyped_tags
@@ -75,17 +80,31 @@ impl TryFrom<&Value> for Photo {
             .map(|t| parse_string(t, "childId").expect("Failed to deserialize a tag json"))
             .collect();
 
+        // Famly scales images to the size given in the URL.
+        let scale = (THUMBNAIL_SIZE as f64 / width.max(height).max(1) as f64).min(1.0);
+        let thumbnail_width = ((width as f64 * scale).round() as u64).max(1);
+        let thumbnail_height = ((height as f64 * scale).round() as u64).max(1);
+
         let p = Photo {
             id: parse_string(json, "imageId")?,
             date,
             url: format!("{0}/{1}x{2}/{3}", prefix, width, height, key),
+            thumbnail_url: format!("{0}/{1}x{2}/{3}", prefix, thumbnail_width, thumbnail_height, key),
             tags,
         };
         Ok(p)
     }
 }
 
+impl Identified for Photo {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
 pub struct Comment {
+    /// The Famly id, or the post id and the position for comments without one.
+    pub id: String,
     pub date: DateTime<Utc>,
     pub author: String,
     pub text: String,
@@ -109,6 +128,8 @@ impl TryFrom<&Value> for Comment {
         };
 
         let c = Comment {
+            // Given by the post if missing.
+            id: json["commentId"].as_str().unwrap_or_default().to_string(),
             date: parse_date(json, "createdDate")?,
             text: parse_string(json, "body")?,
             author,
@@ -117,13 +138,36 @@ impl TryFrom<&Value> for Comment {
     }
 }
 
+/// A file attached to a post, e.g. a PDF newsletter.
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    /// URL of the file (valid only for some time).
+    pub url: String,
+}
+
+impl TryFrom<&Value> for Attachment {
+    type Error = String;
+
+    fn try_from(json: &Value) -> core::result::Result<Self, Self::Error> {
+        let a = Attachment {
+            id: parse_string(json, "fileId")?,
+            name: parse_string(json, "name")?,
+            url: parse_string(json, "url")?,
+        };
+        Ok(a)
+    }
+}
+
 pub struct Post {
+    pub id: String,
     // Famly doesn't store time zones, all dates are in UTC anyways.
     pub date: DateTime<Utc>,
     pub author: String,
     pub text: String,
     pub photos: Vec<Photo>,
     pub comments: Vec<Comment>,
+    pub attachments: Vec<Attachment>,
 }
 
 impl Post {
@@ -147,20 +191,39 @@ impl Post {
             .collect::<String>()
     }
 
+    /// Returns true if at least one photo of the post is tagged with the target child.
+    pub fn is_tagged(&self, child_id: &String) -> bool {
+        self.photos.iter().any(|p| p.is_tagged(child_id))
+    }
+
     pub fn get_file_name(&self) -> String {
         format!("{}.{:02} {}.htm", self.date.year() - 2000, self.date.month(), self.get_title(true))
     }
 
-    /// Converts the raw JSON string to a tuple of:
-    /// * collection of posts
-    /// * an option value: `None` if there were no feed items in the json, otherwise `Some` with
-    /// the *last_item_date* string for fetching of subsequent feed items.
-    pub fn from_feed_json(feed_json: String, child_id: &String) -> Result<(Vec<Post>, Option<String>)> {
+    /// Returns the part of the post needed to list it in indexes.
+    pub fn summarize(&self) -> PostSummary {
+        let mut tags: Vec<String> = self.photos.iter().flat_map(|p| p.tags.iter().cloned()).collect();
+        tags.sort();
+        tags.dedup();
+
+        PostSummary {
+            date: self.date,
+            file_name: self.get_file_name(),
+            title: self.get_title(false),
+            tags,
+        }
+    }
+
+    /// Converts the raw JSON string to a page of posts tagged with any of the target children.
+    pub fn from_feed_json(feed_json: String, child_ids: &[&String]) -> Result<Page<Post>> {
         let parsed_json: Value = serde_json::from_str(&feed_json)?;
         let feed_items = parsed_json["feedItems"].as_array().ok_or("No feedItems array in json")?;
         
         let mut posts = vec![];
+        let mut ids = vec![];
         for f in feed_items {
+            ids.push(parse_string(f, "feedItemId")?);
+
             let class = &f["systemPostTypeClass"];
             if !class.is_null() && class.as_str().unwrap().starts_with("Daycare.") {
                 // Meta post.
@@ -174,8 +237,8 @@ impl Post {
 
             let post: Post = f.try_into().expect("Failed to deserialize a feed item json to a post");
 
-            if post.photos.iter().all(|p| !p.is_tagged(child_id)) {
-                // At least one photo must be tagged with the target child.
+            if !child_ids.iter().any(|c| post.is_tagged(c)) {
+                // At least one photo must be tagged with one of the target children.
                 continue;
             }
 
@@ -184,7 +247,30 @@ impl Post {
 
         let last_item_date = feed_items.last().map(|x| x["createdDate"].as_str().unwrap().to_string());
 
-        Ok((posts, last_item_date))
+        Ok(Page { items: posts, ids, last_item_date })
+    }
+}
+
+impl Identified for Post {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// The part of a post needed to list it in indexes, so that the posts themselves
+/// don't have to be kept in memory once stored.
+pub struct PostSummary {
+    pub date: DateTime<Utc>,
+    pub file_name: String,
+    pub title: String,
+    /// Ids of children tagged in any of the post's photos.
+    tags: Vec<String>,
+}
+
+impl PostSummary {
+    /// Returns true if at least one photo of the post is tagged with the target child.
+    pub fn is_tagged(&self, child_id: &String) -> bool {
+        self.tags.contains(child_id)
     }
 }
 
@@ -197,18 +283,29 @@ impl TryFrom<&Value> for Post {
             .iter()
             .map(|c| c.try_into().expect("Failed to deserialize an image json"))
             .collect();
-        let comments = json["comments"]
+        let id = parse_string(json, "feedItemId")?;
+        let mut comments: Vec<Comment> = json["comments"]
             .as_array().ok_or("No comments array in post json")?
             .iter()
             .map(|c| c.try_into().expect("Failed to deserialize a comment json"))
             .collect();
+        for (position, comment) in comments.iter_mut().enumerate().filter(|(_, c)| c.id.is_empty()) {
+            comment.id = format!("{0}-{1}", id, position);
+        }
+        // Only some posts have files, the ones without a name or URL are skipped.
+        let attachments = json["files"].as_array().map(Vec::as_slice).unwrap_or_default()
+            .iter()
+            .filter_map(|f| f.try_into().ok())
+            .collect();
 
         let p = Post {
+            id,
             date: parse_date(json, "createdDate")?,
             text: parse_string(json, "body")?,
             author: parse_string(&json["sender"], "name")?,
             photos,
             comments,
+            attachments,
         };
         Ok(p)
     }
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// Records raw API responses of one run, page by page, to a folder named after the time of the run.
+/// The recorded responses allow rebuilding the archive without network access.
+pub struct Recorder {
+    root: PathBuf,
+    dir: Option<PathBuf>,
+    pages: usize,
+}
+
+impl Recorder {
+    pub fn new(root: &Path) -> Recorder {
+        Recorder { root: root.to_path_buf(), dir: None, pages: 0 }
+    }
+
+    pub fn save(&mut self, json: &str) -> io::Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => self.dir.insert(create_run_dir(&self.root)?),
+        };
+        self.pages += 1;
+        save(&dir.join(format!("{:04}.json.gz", self.pages)), json)
+    }
+}
+
+/// Creates the folder of a new run. It is named after the time of the run, which sorts it after the earlier runs.
+/// If the name doesn't, e.g. because the last run started within the same second, the name of the last run is used
+/// with a counter.
+fn create_run_dir(root: &Path) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(root)?;
+    loop {
+        let now = Utc::now().format("%Y-%m-%dT%H-%M-%SZ").to_string();
+        let last = list_dir(root)?.into_iter()
+            .filter(|d| d.is_dir())
+            .filter_map(|d| d.file_name()?.to_str().map(String::from))
+            .max();
+        let name = match last {
+            Some(last) if last >= now => {
+                let (time, counter) = last.split_once('Z').unwrap_or((&last, ""));
+                let counter: usize = counter.trim_start_matches('-').parse().unwrap_or(1);
+                format!("{0}Z-{1:03}", time, counter + 1)
+            },
+            _ => now,
+        };
+
+        let dir = root.join(name);
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Writes the response compressed.
+pub fn save(path: &Path, json: &str) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> io::Result<String> {
+    let mut json = String::new();
+    GzDecoder::new(File::open(path)?).read_to_string(&mut json)?;
+    Ok(json)
+}
+
+/// A run recorded by `Recorder`.
+pub struct Run {
+    pub dir: PathBuf,
+    /// Paths of the pages in the order they were loaded.
+    pub pages: Vec<PathBuf>,
+}
+
+/// Returns all runs recorded by `Recorder`, the most recent first. A missing root means no recorded runs.
+pub fn list_runs(root: &Path) -> io::Result<Vec<Run>> {
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut dirs = list_dir(root)?;
+    dirs.retain(|r| r.is_dir());
+    dirs.reverse();
+
+    let mut runs = vec![];
+    for dir in dirs {
+        let mut pages = list_dir(&dir)?;
+        pages.retain(|p| p.to_string_lossy().ends_with(".json.gz"));
+        runs.push(Run { dir, pages });
+    }
+    Ok(runs)
+}
+
+/// Returns paths of all pages recorded by `Recorder`: the most recent runs first,
+/// pages of every run in the order they were loaded.
+pub fn list_pages(root: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(list_runs(root)?.into_iter().flat_map(|r| r.pages).collect())
+}
+
+/// Returns paths of the folder's entries sorted by name.
+fn list_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+
+/// When the daemon runs syncs.
+pub enum Schedule {
+    /// Runs right away and then repeatedly, the interval counts from the start of a run.
+    Interval(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Parses an interval like `90s`, `30m`, `6h` or `1d`.
+    pub fn parse_interval(value: &str) -> Result<Schedule, String> {
+        let value = value.trim();
+        let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+        let number: i64 = number.parse()
+            .map_err(|_| format!("Invalid interval '{0}', expected e.g. 30m, 6h or 1d", value))?;
+        let interval = match unit {
+            "s" => Duration::seconds(number),
+            "m" => Duration::minutes(number),
+            "h" => Duration::hours(number),
+            "d" => Duration::days(number),
+            _ => return Err(format!("Invalid interval '{0}', expected e.g. 30m, 6h or 1d", value)),
+        };
+        if interval < Duration::minutes(1) {
+            return Err(format!("The interval '{0}' is too short, syncs must be at least a minute apart", value));
+        }
+        Ok(Schedule::Interval(interval))
+    }
+
+    /// Parses a cron expression of five fields (minute, hour, day of month, month, day of week), e.g. `0 3 * * *`.
+    pub fn parse_cron(value: &str) -> Result<Schedule, String> {
+        Cron::parse(value).map(Schedule::Cron)
+    }
+
+    /// Returns the time of the run following the one that started at `last_start`, if any, not before `now`.
+    pub fn next_run(&self, last_start: Option<DateTime<Local>>, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Schedule::Interval(interval) => Some(last_start.map(|s| (s + *interval).max(now)).unwrap_or(now)),
+            Schedule::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::Interval(interval) => {
+                let (count, unit) = match interval.num_minutes() {
+                    minutes if minutes % (24 * 60) == 0 => (minutes / (24 * 60), "day"),
+                    minutes if minutes % 60 == 0 => (minutes / 60, "hour"),
+                    minutes => (minutes, "minute"),
+                };
+                match count {
+                    1 => write!(f, "every {0}", unit),
+                    _ => write!(f, "every {0} {1}s", count, unit),
+                }
+            },
+            Schedule::Cron(cron) => write!(f, "cron '{0}'", cron.expression),
+        }
+    }
+}
+
+/// A cron expression in local time. Fields support `*`, lists (`1,15`), ranges (`1-5`) and steps (`*/15`).
+pub struct Cron {
+    expression: String,
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    /// Sunday is both 0 and 7.
+    weekdays: Vec<bool>,
+    /// As usual, if both days and weekdays are restricted (don't start with `*`), matching either is enough.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Invalid cron expression '{0}', expected 5 fields like '0 3 * * *'", expression));
+        }
+        let field = |i: usize, min: usize, max: usize| parse_field(fields[i], min, max)
+            .map_err(|e| format!("Invalid cron expression '{0}': {1}", expression, e));
+
+        let mut weekdays = field(4, 0, 7)?;
+        weekdays[0] |= weekdays[7];
+        Ok(Cron {
+            expression: fields.join(" "),
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches(&self, time: &NaiveDateTime) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes[time.minute() as usize] && self.hours[time.hour() as usize]
+            && self.months[time.month() as usize] && day_matches
+    }
+
+    /// Returns the first matching minute after the time, looking up to a few years ahead.
+    fn next_after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = time.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        (0..4 * 366 * 24 * 60)
+            .map(|m| start + Duration::minutes(m))
+            .filter(|t| self.matches(t))
+            // Times skipped by daylight saving don't exist.
+            .find_map(|t| Local.from_local_datetime(&t).earliest())
+    }
+}
+
+/// Parses a field into flags of the values from 0 to `max`.
+fn parse_field(field: &str, min: usize, max: usize) -> Result<Vec<bool>, String> {
+    let mut values = vec![false; max + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)
+                .ok_or_else(|| format!("invalid step in '{0}'", part))?),
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (parse_value(first, part)?, parse_value(last, part)?),
+                // A single value with a step means a range up to the maximum.
+                None if step > 1 => (parse_value(range, part)?, max),
+                None => (parse_value(range, part)?, parse_value(range, part)?),
+            },
+        };
+        if first < min || last > max || first > last {
+            return Err(format!("'{0}' is out of the range {1}-{2}", part, min, max));
+        }
+        for value in (first..=last).step_by(step) {
+            values[value] = true;
+        }
+    }
+    Ok(values)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("invalid value in '{0}'", part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn march(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        let time = NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        Local.from_local_datetime(&time).unwrap()
+    }
+
+    #[test]
+    fn finds_next_cron_run() {
+        let now = march(14, 10, 30);
+        let next = |expression: &str| Schedule::parse_cron(expression).unwrap().next_run(None, now).unwrap();
+
+        assert_eq!(next("0 3 * * *"), march(15, 3, 0));
+        assert_eq!(next("*/15 10 * * *"), march(14, 10, 45));
+        // Saturday.
+        assert_eq!(next("0 8 * * 6"), march(16, 8, 0));
+        // The 1st of the month or Monday, whichever comes first.
+        assert_eq!(next("0 8 1 * 1"), march(18, 8, 0));
+
+        assert!(Schedule::parse_cron("0 3 * *").is_err());
+        assert!(Schedule::parse_cron("60 3 * * *").is_err());
+        assert!(Schedule::parse_interval("30s").is_err(), "Too short");
+        assert!(Schedule::parse_interval("6 hours").is_err());
+    }
+
+    #[test]
+    fn describes_intervals_in_whole_units() {
+        let describe = |value: &str| Schedule::parse_interval(value).unwrap().to_string();
+
+        assert_eq!(describe("90m"), "every 90 minutes");
+        assert_eq!(describe("120m"), "every 2 hours");
+        assert_eq!(describe("1h"), "every hour");
+        assert_eq!(describe("6h"), "every 6 hours");
+        assert_eq!(describe("1d"), "every day");
+        assert_eq!(describe("48h"), "every 2 days");
+    }
+}
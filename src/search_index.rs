@@ -0,0 +1,23 @@
+use serde_json::json;
+
+use crate::post::Post;
+
+/// Name of the search index next to the index page. It's a script rather than a JSON file,
+/// as browsers don't let pages opened from `file://` fetch files.
+pub const FILE_NAME: &str = "search.js";
+
+/// Renders the search index of the posts, each given with the link to its page relative to the index page.
+/// The index page searches the authors, texts and comments.
+pub fn render(posts: &[(&Post, String)]) -> String {
+    let entries: Vec<_> = posts.iter()
+        .map(|(post, url)| json!({
+            "url": url,
+            "date": post.date.with_timezone(&chrono::Local).format("%d.%m.%Y").to_string(),
+            "title": post.get_title(false),
+            "author": post.author,
+            "text": post.text,
+            "comments": post.comments.iter().map(|c| format!("{0}: {1}", c.author, c.text)).collect::<Vec<_>>(),
+        }))
+        .collect();
+    format!("window.FAMLY_SEARCH_INDEX = {0};\n", serde_json::to_string(&entries).expect("Posts are serializable"))
+}
@@ -0,0 +1,208 @@
+//! Serves the archive over HTTP, with a search over posts and comments and thumbnails scaled on the fly.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use error_chain::error_chain;
+use tiny_http::{Header, Request, Server, StatusCode};
+
+use crate::post::Post;
+use crate::{html, raw, thumbnail};
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+    }
+}
+
+/// Number of requests served at the same time.
+const WORKERS: usize = 4;
+
+/// Most posts shown by a search.
+const MAX_RESULTS: usize = 100;
+
+/// Largest thumbnail in pixels, bigger ones are no cheaper than the photos.
+const MAX_THUMBNAIL_SIZE: u32 = 1600;
+
+/// A post that can be found by the search.
+pub struct SearchablePost {
+    pub post: Post,
+    /// Folder of the child archive that has the post page.
+    pub folder: String,
+}
+
+/// Posts in the search, loaded again whenever a sync has recorded new feed pages.
+struct SearchIndex {
+    pages: Vec<PathBuf>,
+    posts: Vec<SearchablePost>,
+    /// Lowercase texts, authors and comments of the posts.
+    texts: Vec<String>,
+}
+
+type Response = tiny_http::Response<Cursor<Vec<u8>>>;
+
+/// Serves the archive in the output folder at the address until the process is stopped.
+/// `load_posts` returns the recorded posts, newest first.
+pub fn run<F>(output_dir: &Path, address: &str, load_posts: F) -> Result<()>
+    where F: Fn() -> std::result::Result<Vec<SearchablePost>, String> + Sync
+{
+    let server = Server::http(address)
+        .map_err(|e| format!("Cannot serve the archive at {0}: {1}", address, e))?;
+    let index = Mutex::new(SearchIndex { pages: vec![], posts: vec![], texts: vec![] });
+    println!("Serving {0} at http://{1}/, press Ctrl+C to stop", output_dir.display(), server.server_addr());
+
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                while let Ok(request) = server.recv() {
+                    let response = respond(&request, output_dir, &index, &load_posts)
+                        .unwrap_or_else(|e| text_response(500, &e.to_string()));
+                    // The client may be gone already.
+                    let _ = request.respond(response);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond<F>(request: &Request, output_dir: &Path, index: &Mutex<SearchIndex>, load_posts: &F) -> Result<Response>
+    where F: Fn() -> std::result::Result<Vec<SearchablePost>, String>
+{
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let path = urlencoding::decode(path).map_err(|_| "Invalid URL")?;
+    let parameter = |name: &str| query.split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| urlencoding::decode(&v.replace('+', " ")).map(|v| v.into_owned()).unwrap_or_default());
+
+    if path == "/search" {
+        return search(&parameter("q").unwrap_or_default(), output_dir, index, load_posts);
+    }
+
+    // Hidden files keep state and locks, `..` would leave the archive, so would `\` and drive prefixes on Windows.
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.iter().any(|s| s.starts_with('.') || s.contains(['\\', ':'])) {
+        return Ok(text_response(404, "Not found"));
+    }
+    let file_path = segments.iter().fold(output_dir.to_path_buf(), |p, s| p.join(s));
+    // Symlinks must not lead out of the archive either.
+    match (file_path.canonicalize(), output_dir.canonicalize()) {
+        (Ok(file_path), Ok(output_dir)) if file_path.starts_with(&output_dir) => {},
+        _ => return Ok(text_response(404, "Not found")),
+    }
+
+    if file_path.is_dir() {
+        // Relative links of the pages only work from a URL ending with a slash.
+        if !path.ends_with('/') {
+            let location = format!("{0}/", request.url().split('?').next().unwrap_or_default());
+            return Ok(Response::from_data(vec![]).with_status_code(301)
+                .with_header(header("Location", &location)));
+        }
+        if file_path.join("index.htm").is_file() {
+            return serve_file(&file_path.join("index.htm"), None);
+        }
+        return serve_folder(&file_path, &path);
+    }
+
+    let thumbnail_size = parameter("thumbnail").and_then(|s| s.parse().ok()).map(|s: u32| s.min(MAX_THUMBNAIL_SIZE));
+    serve_file(&file_path, thumbnail_size)
+}
+
+/// Serves the file, JPEG photos scaled down if a thumbnail size is given.
+fn serve_file(path: &Path, thumbnail_size: Option<u32>) -> Result<Response> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(text_response(404, "Not found")),
+        Err(e) => return Err(e.into()),
+    };
+    let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    let content_type = match extension.as_str() {
+        "htm" | "html" => "text/html; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "json" => "application/json",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    };
+
+    let content = match thumbnail_size {
+        Some(size) if content_type == "image/jpeg" => thumbnail::resize_jpeg(&content, size)
+            .map_err(|e| format!("Cannot scale {0}: {1}", path.display(), e))?,
+        _ => content,
+    };
+    Ok(Response::from_data(content)
+        .with_header(header("Content-Type", content_type))
+        // Photos never change once downloaded.
+        .with_header(header("Cache-Control", if content_type == "image/jpeg" { "max-age=86400" } else { "no-cache" })))
+}
+
+/// Lists the folder, subfolders first.
+fn serve_folder(path: &Path, url_path: &str) -> Result<Response> {
+    let mut folders = vec![];
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        if entry.path().is_dir() {
+            folders.push(format!("{0}/", name));
+        } else {
+            files.push(name);
+        }
+    }
+    folders.sort();
+    files.sort();
+    folders.extend(files);
+    Ok(html_response(html::render_folder(url_path, &folders)))
+}
+
+/// Finds the posts that contain all words of the query in their text, author or comments.
+fn search<F>(query: &str, output_dir: &Path, index: &Mutex<SearchIndex>, load_posts: &F) -> Result<Response>
+    where F: Fn() -> std::result::Result<Vec<SearchablePost>, String>
+{
+    let mut index = index.lock().unwrap_or_else(|e| e.into_inner());
+    let pages = raw::list_pages(&output_dir.join("raw").join("feed"))?;
+    if pages != index.pages {
+        let posts = load_posts()?;
+        let texts = posts.iter().map(|p| searchable_text(&p.post)).collect();
+        *index = SearchIndex { pages, posts, texts };
+    }
+
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let found: Vec<&SearchablePost> = if words.is_empty() {
+        vec![]
+    } else {
+        index.posts.iter().zip(&index.texts)
+            .filter(|(_, text)| words.iter().all(|w| text.contains(w.as_str())))
+            .map(|(post, _)| post)
+            .collect()
+    };
+    let results: Vec<(&Post, String)> = found.iter().take(MAX_RESULTS).map(|p| (&p.post, p.folder.clone())).collect();
+    Ok(html_response(html::render_search(query, &results, found.len())))
+}
+
+fn searchable_text(post: &Post) -> String {
+    let mut text = format!("{0}\n{1}", post.author, post.text);
+    for comment in &post.comments {
+        text.push_str(&format!("\n{0}\n{1}", comment.author, comment.text));
+    }
+    text.to_lowercase()
+}
+
+fn html_response(html: String) -> Response {
+    Response::from_string(html).with_header(header("Content-Type", "text/html; charset=utf-8"))
+}
+
+fn text_response(status: u16, text: &str) -> Response {
+    Response::from_string(text).with_status_code(StatusCode(status))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("Valid header")
+}
@@ -0,0 +1,27 @@
+//! Graceful shutdown on SIGTERM and SIGINT: long runs check for it between items.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once a shutdown is requested.
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Makes SIGTERM and SIGINT request a shutdown instead of terminating the process right away.
+#[cfg(unix)]
+pub fn install_handlers() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    // Storing to an atomic is safe in a signal handler.
+    unsafe {
+        libc::signal(libc::SIGTERM, handle as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install_handlers() {}
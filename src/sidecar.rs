@@ -0,0 +1,29 @@
+use serde_json::json;
+
+use crate::child_info::ChildInfo;
+use crate::post::Photo;
+
+/// Returns the name of the sidecar of the photo file, e.g. `photo.jpg.json` as in Google Takeout.
+pub fn get_file_name(photo: &Photo) -> String {
+    format!("{0}.json", photo.get_file_name())
+}
+
+/// Renders the Google Takeout JSON sidecar of the photo, which photo importers (Immich, PhotoPrism, ...)
+/// read the date, description and people from. Only the known children are named.
+pub fn render(photo: &Photo, description: Option<&str>, children: &[ChildInfo]) -> String {
+    let people: Vec<_> = children.iter()
+        .filter(|c| photo.is_tagged(&c.id))
+        .map(|c| json!({ "name": c.get_first_name() }))
+        .collect();
+
+    let sidecar = json!({
+        "title": photo.get_file_name(),
+        "description": description.unwrap_or_default(),
+        "photoTakenTime": {
+            "timestamp": photo.date.timestamp().to_string(),
+            "formatted": photo.date.format("%b %-d, %Y, %-I:%M:%S %p UTC").to_string(),
+        },
+        "people": people,
+    });
+    serde_json::to_string_pretty(&sidecar).expect("Sidecars are serializable")
+}
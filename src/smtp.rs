@@ -0,0 +1,234 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use chrono::Local;
+use error_chain::error_chain;
+use native_tls::TlsConnector;
+use serde::Deserialize;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Tls(native_tls::Error);
+        TlsHandshake(native_tls::HandshakeError<TcpStream>);
+    }
+}
+
+/// How the connection to the SMTP server is encrypted.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Security {
+    /// Upgrades the connection with `STARTTLS`, usually on port 587.
+    #[default]
+    Starttls,
+    /// Implicit TLS, usually on port 465.
+    Tls,
+    /// No encryption, for local servers only.
+    None,
+}
+
+pub struct SmtpServer {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+    /// User name and password, if the server requires authentication.
+    pub credentials: Option<(String, String)>,
+}
+
+/// An image attached to the message, referenced by `cid:<content_id>` from the HTML.
+pub struct InlineImage {
+    pub content_id: String,
+    pub file_name: String,
+    pub jpeg: Vec<u8>,
+}
+
+/// Composes a MIME message of the HTML and the images it shows.
+pub fn compose(from: &str, to: &[String], subject: &str, html: &str, images: &[InlineImage]) -> Vec<u8> {
+    let boundary = format!("famly-dl-{}", Local::now().timestamp_nanos());
+    let mut message = format!(
+        "From: {0}\r\nTo: {1}\r\nSubject: {2}\r\nDate: {3}\r\nMIME-Version: 1.0\r\n\
+        Content-Type: multipart/related; boundary=\"{4}\"\r\n\r\n",
+        encode_mailbox(from), to.iter().map(|m| encode_mailbox(m)).collect::<Vec<_>>().join(", "),
+        encode_header(subject), Local::now().to_rfc2822(), boundary);
+
+    message.push_str(&format!(
+        "--{0}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{1}",
+        boundary, encode_base64_lines(html.as_bytes())));
+    for image in images {
+        message.push_str(&format!(
+            "--{0}\r\nContent-Type: image/jpeg\r\nContent-Transfer-Encoding: base64\r\nContent-ID: <{1}>\r\n\
+            Content-Disposition: inline; filename=\"{2}\"\r\n\r\n{3}",
+            boundary, image.content_id, image.file_name, encode_base64_lines(&image.jpeg)));
+    }
+    message.push_str(&format!("--{0}--\r\n", boundary));
+    message.into_bytes()
+}
+
+/// Most bytes of a value in an RFC 2047 encoded-word: its 75 characters leave 63 for Base64 after `=?utf-8?B?` and `?=`.
+const MAX_ENCODED_WORD_BYTES: usize = 45;
+
+/// Encodes non-ASCII header values as described in RFC 2047. Long values are split into several encoded-words
+/// on folded lines, never within a character.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+
+    let mut words = vec![];
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > MAX_ENCODED_WORD_BYTES {
+            words.push(format!("=?utf-8?B?{0}?=", base64::encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?utf-8?B?{0}?=", base64::encode(&chunk)));
+    words.join("\r\n ")
+}
+
+/// Encodes the display name of `Name <address>`, the address itself must be ASCII.
+fn encode_mailbox(mailbox: &str) -> String {
+    match mailbox.find('<') {
+        Some(start) if !mailbox[..start].is_ascii() => {
+            let name = mailbox[..start].trim().trim_matches('"');
+            format!("{0} <{1}>", encode_header(name), address(mailbox))
+        },
+        _ => mailbox.to_string(),
+    }
+}
+
+fn encode_base64_lines(bytes: &[u8]) -> String {
+    let encoded = base64::encode(bytes);
+    let mut lines = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for line in encoded.as_bytes().chunks(76) {
+        lines.push_str(std::str::from_utf8(line).expect("Base64 is ASCII"));
+        lines.push_str("\r\n");
+    }
+    lines
+}
+
+/// Sends the message to the recipients. Addresses may include names, e.g. `Grandma <grandma@example.com>`.
+pub fn send(server: &SmtpServer, from: &str, to: &[String], message: &[u8]) -> Result<()> {
+    let tcp = TcpStream::connect((server.host.as_str(), server.port))
+        .chain_err(|| format!("Cannot connect to the SMTP server {0}:{1}", server.host, server.port))?;
+    tcp.set_read_timeout(Some(Duration::from_secs(60)))?;
+    tcp.set_write_timeout(Some(Duration::from_secs(60)))?;
+
+    match server.security {
+        Security::Tls => {
+            let tls = TlsConnector::new()?.connect(&server.host, tcp)?;
+            let mut connection = Connection::open(tls)?;
+            connection.deliver(server, from, to, message)
+        },
+        Security::Starttls => {
+            let mut connection = Connection::open(tcp)?;
+            connection.command("STARTTLS", &[220])?;
+            let tls = TlsConnector::new()?.connect(&server.host, connection.stream)?;
+            let mut connection = Connection::open_upgraded(tls)?;
+            connection.deliver(server, from, to, message)
+        },
+        Security::None => {
+            let mut connection = Connection::open(tcp)?;
+            connection.deliver(server, from, to, message)
+        },
+    }
+}
+
+struct Connection<S: Read + Write> {
+    stream: S,
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Waits for the greeting and introduces itself.
+    fn open(stream: S) -> Result<Connection<S>> {
+        let mut connection = Connection { stream };
+        connection.expect(&[220])?;
+        connection.command("EHLO famly-dl", &[250])?;
+        Ok(connection)
+    }
+
+    /// Introduces itself again after `STARTTLS`, there's no new greeting.
+    fn open_upgraded(stream: S) -> Result<Connection<S>> {
+        let mut connection = Connection { stream };
+        connection.command("EHLO famly-dl", &[250])?;
+        Ok(connection)
+    }
+
+    fn deliver(&mut self, server: &SmtpServer, from: &str, to: &[String], message: &[u8]) -> Result<()> {
+        if let Some((username, password)) = &server.credentials {
+            let token = base64::encode(format!("\0{0}\0{1}", username, password));
+            self.command(&format!("AUTH PLAIN {0}", token), &[235])
+                .chain_err(|| "The SMTP server rejected the credentials")?;
+        }
+
+        self.command(&format!("MAIL FROM:<{0}>", address(from)), &[250])?;
+        for recipient in to {
+            self.command(&format!("RCPT TO:<{0}>", address(recipient)), &[250, 251])?;
+        }
+        self.command("DATA", &[354])?;
+
+        // Lines starting with a dot get another one, a single dot ends the message.
+        let mut data = Vec::with_capacity(message.len() + 5);
+        for line in message.split_inclusive(|b| *b == b'\n') {
+            if line.starts_with(b".") {
+                data.push(b'.');
+            }
+            data.extend_from_slice(line);
+        }
+        data.extend_from_slice(b".\r\n");
+        self.stream.write_all(&data)?;
+        self.expect(&[250])?;
+
+        self.command("QUIT", &[221])?;
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str, expected: &[u16]) -> Result<String> {
+        self.stream.write_all(format!("{0}\r\n", command).as_bytes())?;
+        // The credentials are not repeated in errors.
+        let command = if command.starts_with("AUTH") { "AUTH" } else { command };
+        self.expect(expected).chain_err(|| format!("SMTP command {0} failed", command))
+    }
+
+    /// Reads the reply, which may span several lines, and checks its code.
+    fn expect(&mut self, expected: &[u16]) -> Result<String> {
+        let mut reply = String::new();
+        loop {
+            let line = self.read_line()?;
+            reply.push_str(&line);
+            reply.push('\n');
+            // The last line of a reply has a space after the code, the others a dash.
+            if line.len() < 4 || line.as_bytes()[3] != b'-' {
+                break;
+            }
+        }
+
+        let code: u16 = reply.get(..3).and_then(|c| c.parse().ok()).unwrap_or_default();
+        if !expected.contains(&code) {
+            return Err(format!("The SMTP server replied: {0}", reply.trim()).into());
+        }
+        Ok(reply)
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = vec![];
+        let mut byte = [0];
+        while !line.ends_with(b"\r\n") {
+            if self.stream.read(&mut byte)? == 0 {
+                return Err("The SMTP server closed the connection".into());
+            }
+            line.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&line[..line.len() - 2]).to_string())
+    }
+}
+
+/// Returns the address part of `Name <address>`.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
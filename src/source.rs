@@ -0,0 +1,151 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::{json, Value};
+
+use crate::child_info::{self, ChildInfo};
+use crate::date_range::DateRange;
+use crate::http::{self, Error, Result};
+use crate::post::{Photo, Post};
+
+/// Where children, posts and photos come from, so syncing doesn't depend on how they are fetched.
+/// Pages are raw JSON responses in the format of the Famly API, so they can be recorded as they are.
+pub trait FeedSource {
+    /// Returns the JSON listing the children of the account.
+    fn child_infos_json(&self) -> Result<String>;
+
+    /// Returns the JSON of the feed page with items older than the date, newest first.
+    fn feed_page(&self, older_than: &Option<String>) -> Result<String>;
+
+    /// Returns the JSON of the page of photos tagged with the child older than the date, newest first.
+    fn tagged_photos_page(&self, child_id: &str, older_than: &Option<String>) -> Result<String>;
+
+    /// Writes the image behind the URL of a post photo or a tagged photo.
+    fn download_image(&self, url: &str, writer: &mut dyn Write) -> Result<()>;
+
+    fn child_infos(&self) -> Result<Vec<ChildInfo>> {
+        child_info::from_json(self.child_infos_json()?)
+            .map_err(|e| Error::from(format!("Failed to deserialize children: {}", e)))
+    }
+
+    /// Returns pages of posts tagged with any of the children, newest first, see `http::paginate`.
+    fn posts<'a>(&'a self, child_ids: &'a [&'a String], range: &'a DateRange)
+        -> impl Iterator<Item = Result<Vec<Post>>> + 'a
+        where
+            Self: Sized,
+    {
+        self.posts_with(child_ids, range, |_| Ok(()))
+    }
+
+    /// Same as `posts`, additionally passing every raw page to `on_page`, e.g. to record it.
+    fn posts_with<'a, R>(&'a self, child_ids: &'a [&'a String], range: &'a DateRange, mut on_page: R)
+        -> impl Iterator<Item = Result<Vec<Post>>> + 'a
+        where
+            Self: Sized,
+            R: FnMut(&str) -> Result<()> + 'a,
+    {
+        http::paginate(range, move |older_than| {
+            let json = self.feed_page(&older_than)?;
+            on_page(&json)?;
+            Post::from_feed_json(json, child_ids)
+                .map_err(|e| Error::from(format!("Failed to deserialize posts: {}", e)))
+        })
+    }
+
+    /// Returns pages of photos tagged with the child, newest first, see `http::paginate`.
+    fn tagged_photos<'a>(&'a self, child_id: &'a str, range: &'a DateRange)
+        -> impl Iterator<Item = Result<Vec<Photo>>> + 'a
+        where
+            Self: Sized,
+    {
+        self.tagged_photos_with(child_id, range, |_| Ok(()))
+    }
+
+    /// Same as `tagged_photos`, additionally passing every raw page to `on_page`.
+    fn tagged_photos_with<'a, R>(&'a self, child_id: &'a str, range: &'a DateRange, mut on_page: R)
+        -> impl Iterator<Item = Result<Vec<Photo>>> + 'a
+        where
+            Self: Sized,
+            R: FnMut(&str) -> Result<()> + 'a,
+    {
+        http::paginate(range, move |older_than| {
+            let json = self.tagged_photos_page(child_id, &older_than)?;
+            on_page(&json)?;
+            Photo::from_json_array(json)
+                .map_err(|e| Error::from(format!("Failed to deserialize tagged photos: {}", e)))
+        })
+    }
+}
+
+/// Serves fixture files the way the Famly API does, for tests. The folder contains:
+/// * `children.json` - the list of children
+/// * `feed.json` - all feed items as `{"feedItems": [...]}`, served page by page
+/// * `tagged/<childId>.json` - all tagged photos of the child as an array, served page by page
+/// * `image.jpg` - served for any image URL
+///
+/// `{{BASE_URL}}` in the fixtures is replaced with the given URL, so photo links can point to a server.
+pub struct FixtureSource {
+    dir: PathBuf,
+    base_url: String,
+    page_size: usize,
+}
+
+impl FixtureSource {
+    pub fn new(dir: &Path, base_url: &str, page_size: usize) -> FixtureSource {
+        FixtureSource { dir: dir.to_path_buf(), base_url: base_url.to_string(), page_size }
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Value>> {
+        let text = match std::fs::read_to_string(self.dir.join(name)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let json = serde_json::from_str(&text.replace("{{BASE_URL}}", &self.base_url))
+            .map_err(|e| format!("Invalid fixture {0}: {1}", name, e))?;
+        Ok(Some(json))
+    }
+
+    /// Returns items strictly older than the date, newest first.
+    fn paginate(&self, items: &Value, date_property: &str, older_than: &Option<String>) -> Vec<Value> {
+        let mut items = items.as_array().cloned().unwrap_or_default();
+        items.sort_by_key(|i| std::cmp::Reverse(parse_date(&i[date_property])));
+
+        let older_than = older_than.as_deref().and_then(|d| DateTime::parse_from_rfc3339(d).ok());
+        items.into_iter()
+            .filter(|i| match (older_than, parse_date(&i[date_property])) {
+                (Some(o), Some(d)) => d < o,
+                _ => true,
+            })
+            .take(self.page_size)
+            .collect()
+    }
+}
+
+impl FeedSource for FixtureSource {
+    fn child_infos_json(&self) -> Result<String> {
+        let json = self.read("children.json")?.ok_or("No children.json fixture")?;
+        Ok(json.to_string())
+    }
+
+    fn feed_page(&self, older_than: &Option<String>) -> Result<String> {
+        let feed = self.read("feed.json")?.unwrap_or_default();
+        let page = self.paginate(&feed["feedItems"], "createdDate", older_than);
+        Ok(json!({ "feedItems": page }).to_string())
+    }
+
+    fn tagged_photos_page(&self, child_id: &str, older_than: &Option<String>) -> Result<String> {
+        let photos = self.read(&format!("tagged/{}.json", child_id))?.unwrap_or_default();
+        Ok(Value::Array(self.paginate(&photos, "createdAt", older_than)).to_string())
+    }
+
+    fn download_image(&self, _url: &str, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(&std::fs::read(self.dir.join("image.jpg"))?)?;
+        Ok(())
+    }
+}
+
+fn parse_date(value: &Value) -> Option<DateTime<FixedOffset>> {
+    value.as_str().and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+}
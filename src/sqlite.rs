@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use error_chain::error_chain;
+use rusqlite::{params, Connection, Transaction};
+
+use crate::child_info::ChildInfo;
+use crate::date_range::format_timestamp;
+use crate::post::{Photo, Post};
+
+error_chain! {
+    foreign_links {
+        Sqlite(rusqlite::Error);
+    }
+}
+
+/// Name of the database in the output folder.
+pub const FILE_NAME: &str = "famly.sqlite";
+
+/// Version of the schema, stored as `user_version` of the database.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE children (
+    id TEXT PRIMARY KEY,
+    first_name TEXT NOT NULL,
+    full_name TEXT NOT NULL,
+    institution TEXT NOT NULL
+);
+CREATE TABLE posts (
+    id TEXT PRIMARY KEY,
+    date TEXT NOT NULL,
+    author TEXT NOT NULL,
+    text TEXT NOT NULL,
+    file_name TEXT NOT NULL
+);
+CREATE TABLE photos (
+    id TEXT PRIMARY KEY,
+    -- NULL for tagged photos not shared in a post.
+    post_id TEXT REFERENCES posts(id),
+    date TEXT NOT NULL,
+    file_name TEXT NOT NULL
+);
+CREATE TABLE photo_tags (
+    photo_id TEXT NOT NULL REFERENCES photos(id),
+    child_id TEXT NOT NULL,
+    PRIMARY KEY (photo_id, child_id)
+);
+CREATE TABLE comments (
+    id TEXT PRIMARY KEY,
+    post_id TEXT NOT NULL REFERENCES posts(id),
+    date TEXT NOT NULL,
+    author TEXT NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE attachments (
+    id TEXT PRIMARY KEY,
+    post_id TEXT NOT NULL REFERENCES posts(id),
+    name TEXT NOT NULL,
+    url TEXT NOT NULL
+);
+CREATE INDEX photos_post_id ON photos(post_id);
+CREATE INDEX photo_tags_child_id ON photo_tags(child_id);
+CREATE INDEX comments_post_id ON comments(post_id);
+CREATE INDEX attachments_post_id ON attachments(post_id);
+
+-- Full-text search over the posts, kept up to date by the triggers.
+CREATE VIRTUAL TABLE posts_fts USING fts5(author, text, content='posts', content_rowid='rowid');
+CREATE TRIGGER posts_ai AFTER INSERT ON posts BEGIN
+    INSERT INTO posts_fts(rowid, author, text) VALUES (new.rowid, new.author, new.text);
+END;
+CREATE TRIGGER posts_ad AFTER DELETE ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, author, text) VALUES ('delete', old.rowid, old.author, old.text);
+END;
+CREATE TRIGGER posts_au AFTER UPDATE ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, author, text) VALUES ('delete', old.rowid, old.author, old.text);
+    INSERT INTO posts_fts(rowid, author, text) VALUES (new.rowid, new.author, new.text);
+END;
+";
+
+/// Adds the children, posts and tagged photos to the database, creating it if needed. Rows of earlier runs are kept,
+/// so the database covers everything ever downloaded, rows of the given items are replaced.
+pub fn export(path: &Path, children: &[ChildInfo], posts: &[Post], tagged_photos: &[&Photo]) -> Result<()> {
+    let mut connection = Connection::open(path)
+        .chain_err(|| format!("Cannot open the database {0}", path.display()))?;
+    let transaction = connection.transaction()?;
+
+    let version: i64 = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    match version {
+        0 => {
+            transaction.execute_batch(SCHEMA)?;
+            transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        },
+        SCHEMA_VERSION => {},
+        _ => return Err(format!(
+            "The database {0} was created by a newer version of famly-dl, delete it to start over", path.display()).into()),
+    }
+
+    for child in children {
+        transaction.execute(
+            "INSERT INTO children (id, first_name, full_name, institution) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (id) DO UPDATE SET
+                first_name = excluded.first_name, full_name = excluded.full_name, institution = excluded.institution",
+            params![child.id, child.get_first_name(), child.full_name_with_institution, child.institution])?;
+    }
+
+    for post in posts {
+        insert_post(&transaction, post)?;
+    }
+    for photo in tagged_photos {
+        insert_photo(&transaction, photo, None)?;
+    }
+
+    transaction.commit()?;
+    Ok(())
+}
+
+fn insert_post(transaction: &Transaction, post: &Post) -> Result<()> {
+    // An update rather than a replacement keeps the row id, which the full-text index refers to.
+    transaction.execute(
+        "INSERT INTO posts (id, date, author, text, file_name) VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (id) DO UPDATE SET
+            date = excluded.date, author = excluded.author, text = excluded.text, file_name = excluded.file_name",
+        params![post.id, format_timestamp(&post.date), post.author, post.text, post.get_file_name()])?;
+
+    for photo in &post.photos {
+        insert_photo(transaction, photo, Some(&post.id))?;
+    }
+
+    // Comments may have been deleted since.
+    transaction.execute("DELETE FROM comments WHERE post_id = ?1", [&post.id])?;
+    for comment in &post.comments {
+        transaction.execute(
+            "INSERT OR REPLACE INTO comments (id, post_id, date, author, text) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![comment.id, post.id, format_timestamp(&comment.date), comment.author, comment.text])?;
+    }
+
+    transaction.execute("DELETE FROM attachments WHERE post_id = ?1", [&post.id])?;
+    for attachment in &post.attachments {
+        transaction.execute(
+            "INSERT OR REPLACE INTO attachments (id, post_id, name, url) VALUES (?1, ?2, ?3, ?4)",
+            params![attachment.id, post.id, attachment.name, attachment.url])?;
+    }
+    Ok(())
+}
+
+fn insert_photo(transaction: &Transaction, photo: &Photo, post_id: Option<&String>) -> Result<()> {
+    // Tagged photos are often shared in a post too, they keep the link to it.
+    transaction.execute(
+        "INSERT INTO photos (id, post_id, date, file_name) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (id) DO UPDATE SET
+            post_id = coalesce(excluded.post_id, photos.post_id), date = excluded.date, file_name = excluded.file_name",
+        params![photo.id, post_id, format_timestamp(&photo.date), photo.get_file_name()])?;
+
+    transaction.execute("DELETE FROM photo_tags WHERE photo_id = ?1", [&photo.id])?;
+    for child_id in &photo.tags {
+        transaction.execute("INSERT OR IGNORE INTO photo_tags (photo_id, child_id) VALUES (?1, ?2)",
+            params![photo.id, child_id])?;
+    }
+    Ok(())
+}
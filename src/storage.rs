@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use error_chain::error_chain;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        HttpRequest(reqwest::Error);
+    }
+}
+
+/// Where the archive is written. Paths are relative to the root of the archive and separated by `/`.
+pub trait Storage {
+    /// Writes the file, replacing an existing one, and creates missing folders.
+    fn put(&self, path: &str, content: &[u8]) -> Result<()>;
+
+    fn exists(&self, path: &str) -> Result<bool>;
+
+    /// Makes the file at `path` have the content of `target`, sharing the data where possible.
+    fn link(&self, target: &str, path: &str) -> Result<()>;
+
+    /// Lists all files in the folder and its subfolders, paths are relative to the root of the archive.
+    fn list(&self, dir: &str) -> Result<Vec<String>>;
+}
+
+/// Writes the archive to a local folder.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &Path) -> LocalStorage {
+        LocalStorage { root: root.to_path_buf() }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        path.split('/').fold(self.root.clone(), |p, part| p.join(part))
+    }
+}
+
+impl Storage for LocalStorage {
+    /// Writes to a temporary file first, so a failed write doesn't leave a broken file behind.
+    fn put(&self, path: &str, content: &[u8]) -> Result<()> {
+        let path = self.resolve(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // A photo and its sidecar must not share the temporary file.
+        let mut part_path = path.as_os_str().to_owned();
+        part_path.push(".part");
+        if let Err(e) = std::fs::write(&part_path, content) {
+            let _ = std::fs::remove_file(&part_path);
+            return Err(e.into());
+        }
+        std::fs::rename(part_path, path)?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.resolve(path).exists())
+    }
+
+    /// Creates a hard link.
+    fn link(&self, target: &str, path: &str) -> Result<()> {
+        let path = self.resolve(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::hard_link(self.resolve(target), path)?;
+        Ok(())
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<String>> {
+        let mut files = vec![];
+        list_dir(&self.resolve(dir), dir, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn list_dir(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = if prefix.is_empty() { name } else { format!("{0}/{1}", prefix, name) };
+        if entry.file_type()?.is_dir() {
+            list_dir(&entry.path(), &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
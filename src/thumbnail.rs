@@ -0,0 +1,15 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, ImageResult};
+
+/// Scales the JPEG image down to fit into a square of `max_size` pixels, smaller images are only re-encoded.
+pub fn resize_jpeg(jpeg: &[u8], max_size: u32) -> ImageResult<Vec<u8>> {
+    let mut image = image::load_from_memory(jpeg)?;
+    if image.width() > max_size || image.height() > max_size {
+        image = image.thumbnail(max_size, max_size);
+    }
+
+    let rgb = image.to_rgb8();
+    let mut resized = vec![];
+    JpegEncoder::new_with_quality(&mut resized, 80).encode(&rgb, rgb.width(), rgb.height(), ColorType::Rgb8)?;
+    Ok(resized)
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::ChaCha20Poly1305;
+use error_chain::error_chain;
+use serde::Deserialize;
+
+use crate::console;
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+    }
+}
+
+/// Marks the format version of the encrypted token cache.
+const CACHE_MAGIC: &[u8] = b"FAMLYTC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where the access token (or the account password) comes from.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TokenSource {
+    /// The token itself.
+    Value(String),
+    /// Name of the environment variable holding the token.
+    Env(String),
+    /// File holding the token, it must not be accessible by other users.
+    File(PathBuf),
+    /// Shell command printing the token, e.g. `pass famly`.
+    Command(String),
+    /// Token cache encrypted with a passphrase, see `store_cache`.
+    Cache(PathBuf),
+}
+
+impl TokenSource {
+    pub fn read(&self) -> Result<String> {
+        let token = match self {
+            TokenSource::Value(token) => token.clone(),
+            TokenSource::Env(name) => std::env::var(name)
+                .map_err(|_| format!("Environment variable '{0}' is not set", name))?,
+            TokenSource::File(path) => read_file(path)?,
+            TokenSource::Command(command) => run_command(command)?,
+            TokenSource::Cache(path) => read_cache(path)?,
+        };
+        Ok(token.trim().to_string())
+    }
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    check_permissions(path)?;
+    let token = std::fs::read_to_string(path)
+        .chain_err(|| format!("Cannot read the token file {0}", path.display()))?;
+    Ok(token)
+}
+
+/// Refuses files that other users can read or modify.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(path)
+        .chain_err(|| format!("Cannot read the token file {0}", path.display()))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "The token file {0} is accessible by other users (mode {1:o}), restrict it with `chmod 600`",
+            path.display(), mode & 0o777).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<()> {
+    // Windows ACLs are inherited from the user profile folder.
+    Ok(())
+}
+
+/// Runs the command through the shell and returns the first line it prints.
+fn run_command(command: &str) -> Result<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }.chain_err(|| format!("Cannot run the token command '{0}'", command))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "The token command '{0}' failed ({1}) {2}",
+            command, output.status, stderr.trim()).trim_end().into());
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("The token command '{0}' printed invalid UTF-8", command))?;
+    let token = stdout.lines().next().unwrap_or_default().to_string();
+    if token.trim().is_empty() {
+        return Err(format!("The token command '{0}' printed nothing", command).into());
+    }
+    Ok(token)
+}
+
+fn read_cache(path: &Path) -> Result<String> {
+    let data = std::fs::read(path)
+        .chain_err(|| format!("Cannot read the token cache {0}", path.display()))?;
+    if data.len() < CACHE_MAGIC.len() + SALT_LEN + NONCE_LEN || !data.starts_with(CACHE_MAGIC) {
+        return Err(format!("{0} is not a token cache", path.display()).into());
+    }
+
+    let (salt, rest) = data[CACHE_MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let passphrase = read_passphrase(&format!("Passphrase for {0}: ", path.display()))?;
+    let cipher = create_cipher(&passphrase, salt)?;
+    let token = cipher.decrypt(nonce.into(), ciphertext)
+        .map_err(|_| format!("Cannot decrypt the token cache {0}: wrong passphrase?", path.display()))?;
+
+    String::from_utf8(token).map_err(|_| "The cached token is not valid UTF-8".into())
+}
+
+/// Encrypts the token with a passphrase and writes it to the cache file.
+pub fn store_cache(path: &Path, token: &str, passphrase: &str) -> Result<()> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let cipher = create_cipher(passphrase, &salt)?;
+    let ciphertext = cipher.encrypt(&nonce, token.trim().as_bytes())
+        .map_err(|_| "Cannot encrypt the token")?;
+
+    let mut file = create_private_file(path)
+        .chain_err(|| format!("Cannot create the token cache {0}", path.display()))?;
+    file.write_all(CACHE_MAGIC)?;
+    file.write_all(&salt)?;
+    file.write_all(&nonce)?;
+    file.write_all(&ciphertext)?;
+    Ok(())
+}
+
+fn create_cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Cannot derive the key from the passphrase: {0}", e))?;
+    Ok(ChaCha20Poly1305::new(&key.into()))
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // The mode only applies to new files, an existing one keeps its permissions otherwise.
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::File::create(path)
+}
+
+/// Takes the passphrase from `FAMLY_TOKEN_PASSPHRASE` or asks the user for it.
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("FAMLY_TOKEN_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    if !console::is_interactive() {
+        return Err("A passphrase is required, but input is not interactive: set FAMLY_TOKEN_PASSPHRASE".into());
+    }
+    Ok(rpassword::prompt_password(prompt)?)
+}
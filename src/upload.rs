@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use error_chain::error_chain;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::hex;
+use crate::search_index;
+use crate::storage::{self, LocalStorage, Storage};
+use crate::webdav::WebDavFolder;
+
+error_chain! {
+    links {
+        Storage(storage::Error, storage::ErrorKind);
+    }
+    foreign_links {
+        Io(std::io::Error);
+        Json(serde_json::Error);
+    }
+}
+
+/// Name of the file in the output folder remembering what was uploaded.
+const STATE_FILE_NAME: &str = ".upload-state.json";
+
+/// Files uploaded to a target so far, to upload only new and changed files.
+#[derive(Serialize, Deserialize, Default)]
+struct UploadState {
+    /// Identifies the target, the state is discarded when the target changes.
+    target: String,
+    files: BTreeMap<String, FileState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct FileState {
+    size: u64,
+    /// Seconds since the Unix epoch.
+    modified: u64,
+    sha256: String,
+}
+
+/// Copies new and changed files of the archive folders (and the family index with its search) to the target.
+/// Recorded API responses are not uploaded. Returns the number of uploaded files.
+pub fn upload(output_dir: &Path, folders: &[String], target: &WebDavFolder, target_name: &str) -> Result<usize> {
+    let state_path = output_dir.join(STATE_FILE_NAME);
+    let mut state = read_state(&state_path, target_name)?;
+    let local = LocalStorage::new(output_dir);
+
+    let mut paths = vec![];
+    for family_file in ["index.htm", search_index::FILE_NAME] {
+        if output_dir.join(family_file).exists() {
+            paths.push(family_file.to_string());
+        }
+    }
+    for folder in folders {
+        let raw_dir = format!("{0}/raw/", folder);
+        paths.extend(local.list(folder)?.into_iter().filter(|p| !p.starts_with(&raw_dir)));
+    }
+
+    let mut uploaded = 0;
+    for path in paths {
+        let local_path = path.split('/').fold(output_dir.to_path_buf(), |p, part| p.join(part));
+        let metadata = std::fs::metadata(&local_path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+
+        let known = state.files.get(&path);
+        if let Some(known) = known {
+            if known.size == metadata.len() && known.modified == modified {
+                continue;
+            }
+        }
+
+        // Posts are rewritten on every run, so the content decides if they changed.
+        let content = std::fs::read(&local_path)?;
+        let file = FileState { size: metadata.len(), modified, sha256: hex(&Sha256::digest(&content)) };
+        let is_changed = known.map(|k| k.sha256 != file.sha256).unwrap_or(true);
+        if is_changed {
+            target.put(&path, &content)?;
+            uploaded += 1;
+        }
+
+        state.files.insert(path, file);
+        if is_changed && uploaded % 50 == 0 {
+            println!("{} files uploaded...", uploaded);
+            // Saved as it goes, so an interrupted upload resumes where it stopped.
+            write_state(&state_path, &state)?;
+        }
+    }
+
+    write_state(&state_path, &state)?;
+    Ok(uploaded)
+}
+
+fn read_state(path: &Path, target_name: &str) -> Result<UploadState> {
+    let state: UploadState = match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .chain_err(|| format!("Invalid upload state {0}, delete it to upload everything again", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => UploadState::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if state.target != target_name {
+        return Ok(UploadState { target: target_name.to_string(), files: BTreeMap::new() });
+    }
+    Ok(state)
+}
+
+fn write_state(path: &Path, state: &UploadState) -> Result<()> {
+    let part_path = path.with_extension("part");
+    std::fs::write(&part_path, serde_json::to_string(state)?)?;
+    std::fs::rename(part_path, path)?;
+    Ok(())
+}
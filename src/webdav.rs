@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
+
+use crate::storage::Result;
+
+/// A folder of a WebDAV server the archive is uploaded to, e.g. on Nextcloud.
+pub struct WebDavFolder {
+    client: Client,
+    /// URL of the root folder, ending with `/`.
+    url: Url,
+    username: String,
+    password: String,
+    /// Folders known to exist, so they are created only once.
+    folders: RefCell<HashSet<String>>,
+}
+
+impl WebDavFolder {
+    /// `url` is the root folder of the archive, e.g. `https://cloud.example.com/remote.php/dav/files/<user>/Famly`.
+    pub fn new(url: &str, username: &str, password: &str) -> Result<WebDavFolder> {
+        let url = Url::parse(&format!("{}/", url.trim_end_matches('/')))
+            .map_err(|e| format!("Invalid WebDAV URL '{0}': {1}", url, e))?;
+        Ok(WebDavFolder {
+            client: Client::new(),
+            url,
+            username: username.to_string(),
+            password: password.to_string(),
+            folders: RefCell::new(HashSet::new()),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        let encoded = path.split('/').map(|s| urlencoding::encode(s).into_owned()).collect::<Vec<_>>().join("/");
+        self.url.join(&encoded).map_err(|e| format!("Invalid path '{0}': {1}", path, e).into())
+    }
+
+    fn request(&self, method: &str, path: &str) -> Result<RequestBuilder> {
+        let method = Method::from_bytes(method.as_bytes()).expect("Valid WebDAV method");
+        Ok(self.client.request(method, self.url(path)?).basic_auth(&self.username, Some(&self.password)))
+    }
+
+    /// Creates the root folder and the parent folders of the file, unless they are known to exist.
+    fn create_parents(&self, path: &str) -> Result<()> {
+        let parents = path.split('/').rev().skip(1).collect::<Vec<_>>();
+        let mut folder = String::new();
+        for part in std::iter::once("").chain(parents.into_iter().rev()) {
+            if !part.is_empty() {
+                folder.push_str(part);
+                folder.push('/');
+            }
+            if self.folders.borrow().contains(&folder) {
+                continue;
+            }
+
+            let response = self.request("MKCOL", &folder)?.send()?;
+            // 405 means the folder exists already.
+            if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                check(response, &folder)?;
+            }
+            self.folders.borrow_mut().insert(folder.clone());
+        }
+        Ok(())
+    }
+
+    /// Writes the file, creating its folders as needed.
+    pub fn put(&self, path: &str, content: &[u8]) -> Result<()> {
+        self.create_parents(path)?;
+        let response = self.request("PUT", path)?.body(content.to_vec()).send()?;
+        check(response, path)?;
+        Ok(())
+    }
+}
+
+fn check(response: Response, path: &str) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(format!("The WebDAV server rejected the credentials (HTTP {0})", status.as_u16()).into());
+    }
+    Err(format!("WebDAV request for '{0}' failed (HTTP {1})", path, status.as_u16()).into())
+}
+
@@ -0,0 +1,44 @@
+/// Returns the content of all elements with the local name, whatever their namespace prefix is.
+/// S3 responses are simple enough to not need an XML parser. Nested elements
+/// of the same name are not supported.
+pub fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut values = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let tag_end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..tag_end];
+        let full_name = tag.split_whitespace().next().unwrap_or_default();
+        let local_name = full_name.rsplit(':').next().unwrap_or_default();
+        if local_name != name || tag.ends_with('/') {
+            continue;
+        }
+
+        rest = &rest[tag_end + 1..];
+        let close = format!("</{}>", full_name);
+        match rest.find(&close) {
+            Some(end) => {
+                values.push(&rest[..end]);
+                rest = &rest[end + close.len()..];
+            },
+            None => break,
+        }
+    }
+    values
+}
+
+/// Returns the unescaped text of all elements with the local name.
+pub fn values(xml: &str, name: &str) -> Vec<String> {
+    elements(xml, name).into_iter().map(unescape).collect()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
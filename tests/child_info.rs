@@ -0,0 +1,19 @@
+//! Tests of the child folder names.
+
+use famly_dl::ChildInfo;
+
+fn child(id: &str, name: &str) -> ChildInfo {
+    ChildInfo { id: id.to_string(), full_name_with_institution: name.to_string(), institution: "Kita".to_string() }
+}
+
+#[test]
+fn sanitizes_folder_aliases() {
+    let children = [child("1", "../../etc Doe"), child("2", "Jürgen Doe"), child("3", "Jürgen Roe"), child("4", ""),
+        child("5", "Raw Doe"), child("6", "Export Doe")];
+
+    assert_eq!(children[0].get_alias(&children), "______etc");
+    assert_eq!(children[1].get_alias(&children), "Jürgen_2");
+    assert_eq!(children[3].get_alias(&children), "4");
+    assert_eq!(children[4].get_alias(&children), "Raw_5", "Taken by the recorded responses");
+    assert_eq!(children[5].get_alias(&children), "Export_6");
+}
@@ -0,0 +1,225 @@
+//! Helpers to run `famly-dl` against the mock server with the fixtures in `tests/fixtures/mock`.
+
+// Every test file uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tiny_http::{Response, Server};
+
+pub const EMMA: &str = "2b7c4c1e-5d1f-4a35-9d8e-0f1a2b3c4d5e";
+pub const NOAH: &str = "9f8e7d6c-5b4a-4392-8170-6f5e4d3c2b1a";
+
+pub struct MockServer {
+    _process: Running,
+    pub url: String,
+}
+
+impl MockServer {
+    pub fn start(args: &[&str]) -> MockServer {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mock");
+        let process = Running::start(Command::new(env!("CARGO_BIN_EXE_famly-mock-server"))
+            .arg("--fixtures").arg(fixtures)
+            .args(["--page-size", "2"])
+            .args(args));
+        let url = process.wait_for_url();
+        MockServer { _process: process, url }
+    }
+}
+
+/// A process started by a test, killed when the test ends even if it fails. What it prints is collected.
+pub struct Running {
+    process: Child,
+    stdout: Arc<Mutex<String>>,
+    stderr: Arc<Mutex<String>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl Running {
+    pub fn start(command: &mut Command) -> Running {
+        let mut process = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the process");
+
+        let (stdout, stdout_reader) = collect(process.stdout.take().unwrap());
+        let (stderr, stderr_reader) = collect(process.stderr.take().unwrap());
+        Running { process, stdout, stderr, readers: vec![stdout_reader, stderr_reader] }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.process.id()
+    }
+
+    /// Waits until the process prints a URL, e.g. the one it listens at after binding port 0, and returns it
+    /// without a trailing slash.
+    pub fn wait_for_url(&self) -> String {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let stdout = self.stdout.lock().unwrap().clone();
+            if let Some(start) = stdout.find("http://") {
+                let url = &stdout[start..];
+                if let Some(end) = url.find(char::is_whitespace) {
+                    return url[..end].trim_end_matches([',', '/']).to_string();
+                }
+            }
+            assert!(Instant::now() < deadline, "No URL printed: {}{}", stdout, self.stderr.lock().unwrap());
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Waits for the process to exit, returns its status, stdout and stderr.
+    pub fn wait(&mut self) -> (ExitStatus, String, String) {
+        let status = self.process.wait().unwrap();
+        for reader in self.readers.drain(..) {
+            reader.join().unwrap();
+        }
+        (status, self.stdout.lock().unwrap().clone(), self.stderr.lock().unwrap().clone())
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Reads the pipe into a string in a thread of its own, so the process never blocks on a full pipe.
+fn collect<R: Read + Send + 'static>(pipe: R) -> (Arc<Mutex<String>>, JoinHandle<()>) {
+    let text = Arc::new(Mutex::new(String::new()));
+    let reader_text = text.clone();
+    let reader = thread::spawn(move || {
+        for line in BufReader::new(pipe).lines().map_while(|l| l.ok()) {
+            let mut text = reader_text.lock().unwrap();
+            text.push_str(&line);
+            text.push('\n');
+        }
+    });
+    (text, reader)
+}
+
+/// A request received by a [`StubServer`].
+pub struct Received {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Received {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+/// Shows the request as `METHOD url body`.
+impl fmt::Display for Received {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.method, self.url, self.text())
+    }
+}
+
+/// A local HTTP server standing in for a third-party service, e.g. Immich or a webhook. It records the requests
+/// and answers each with `respond`.
+pub struct StubServer {
+    server: Arc<Server>,
+    pub url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl StubServer {
+    pub fn start<F>(mut respond: F) -> StubServer
+        where F: FnMut(&Received) -> Response<Cursor<Vec<u8>>> + Send + 'static
+    {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr());
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let (stub_server, stub_received) = (server.clone(), received.clone());
+        thread::spawn(move || {
+            for mut request in stub_server.incoming_requests() {
+                let mut body = vec![];
+                request.as_reader().read_to_end(&mut body).unwrap();
+                let received = Received {
+                    method: request.method().to_string(),
+                    url: request.url().to_string(),
+                    headers: request.headers().iter().map(|h| (h.field.to_string(), h.value.to_string())).collect(),
+                    body,
+                };
+                let response = respond(&received);
+                stub_received.lock().unwrap().push(received);
+                // The client may be gone already.
+                let _ = request.respond(response);
+            }
+        });
+
+        StubServer { server, url, received }
+    }
+
+    /// Removes the recorded requests starting with the prefix when shown as `METHOD url body` and returns them.
+    pub fn take(&self, prefix: &str) -> Vec<Received> {
+        let mut received = self.received.lock().unwrap();
+        let (taken, rest) = received.drain(..).partition(|r| r.to_string().starts_with(prefix));
+        *received = rest;
+        taken
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+pub fn sync(server: &MockServer, token: &str, output_dir: &Path) -> Output {
+    run(server, token, output_dir, &[])
+}
+
+/// Runs `famly-dl` with the arguments for all children, the configuration is read from `<output_dir>/famly-dl`.
+pub fn run(server: &MockServer, token: &str, output_dir: &Path, args: &[&str]) -> Output {
+    command(server, token, output_dir, args)
+        .output()
+        .expect("Failed to run famly-dl")
+}
+
+/// Prepares `famly-dl` like [`run`] does, for tests that need to interact with the running process.
+pub fn command(server: &MockServer, token: &str, output_dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_famly-dl"));
+    command
+        .args(args)
+        .arg("--all-children")
+        .env_clear()
+        .env("FAMLY_ACCESS_TOKEN", token)
+        .env("FAMLY_API_URL", &server.url)
+        .env("FAMLY_TARGET_FOLDER", output_dir)
+        .env("XDG_CONFIG_HOME", output_dir)
+        .env("HOME", output_dir)
+        .stdin(Stdio::null());
+    command
+}
+
+/// Returns the files in the folder, none if it doesn't exist.
+pub fn list_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.map(|e| e.unwrap().path()).collect(),
+        Err(_) => vec![],
+    };
+    files.sort();
+    files
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
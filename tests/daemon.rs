@@ -0,0 +1,59 @@
+//! Tests of the daemon mode and of the lock preventing overlapping runs.
+
+mod common;
+
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+use common::{command, stderr, sync, MockServer, Running, EMMA};
+use serde_json::Value;
+use tempfile::TempDir;
+
+#[cfg(unix)]
+#[test]
+fn syncs_on_schedule_until_sigterm() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+    let mut daemon = Running::start(&mut command(&server, "mock-token", dir.path(),
+        &["daemon", "--interval", "1h", "--status-address", "127.0.0.1:0"]));
+    let status_url = daemon.wait_for_url();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let status = loop {
+        assert!(Instant::now() < deadline, "The first sync hasn't finished in time");
+        if let Ok(response) = reqwest::blocking::get(&status_url) {
+            let status: Value = serde_json::from_str(&response.text().unwrap()).unwrap();
+            if status["runs"] == 1 {
+                break status;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert_eq!(status["state"], "waiting");
+    assert_eq!(status["schedule"], "every hour");
+    assert_eq!(status["last_run"]["success"], true, "{}", status);
+    assert!(status["next_run"].is_string());
+    assert!(dir.path().join(EMMA).join("index.htm").is_file());
+
+    unsafe { libc::kill(daemon.id() as libc::pid_t, libc::SIGTERM) };
+    let (status, stdout, stderr) = daemon.wait();
+    assert!(status.success(), "{}", stderr);
+    assert!(stdout.ends_with("Stopped\n"), "{}", stdout);
+}
+
+#[test]
+fn refuses_to_run_while_another_run_is_writing() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+    let lock = File::create(dir.path().join(".famly-dl.lock")).unwrap();
+    lock.lock().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Another run of famly-dl"), "{}", stderr(&output));
+
+    drop(lock);
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.path().join(EMMA).join("index.htm").is_file());
+}
@@ -0,0 +1,146 @@
+//! Tests of the email digest against a local SMTP sink.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use common::{run, stderr, sync, MockServer};
+use tempfile::TempDir;
+
+struct Message {
+    recipients: Vec<String>,
+    data: String,
+}
+
+/// Accepts every message and records it.
+struct SmtpSink {
+    port: u16,
+    messages: Arc<Mutex<Vec<Message>>>,
+}
+
+impl SmtpSink {
+    fn start() -> SmtpSink {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages = Arc::new(Mutex::new(vec![]));
+
+        let sink_messages = messages.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut recipients = vec![];
+                stream.write_all(b"220 sink\r\n").unwrap();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 {
+                        break;
+                    }
+                    let reply: &[u8] = match line.trim_end() {
+                        l if l.starts_with("EHLO") => b"250-sink\r\n250 OK\r\n",
+                        l if l.starts_with("RCPT TO:") => {
+                            recipients.push(l["RCPT TO:".len()..].to_string());
+                            b"250 OK\r\n"
+                        },
+                        "DATA" => {
+                            stream.write_all(b"354 Go ahead\r\n").unwrap();
+                            let mut data = String::new();
+                            while !data.ends_with("\r\n.\r\n") {
+                                reader.read_line(&mut data).unwrap();
+                            }
+                            sink_messages.lock().unwrap().push(Message { recipients: recipients.clone(), data });
+                            b"250 Queued\r\n"
+                        },
+                        "QUIT" => {
+                            stream.write_all(b"221 Bye\r\n").unwrap();
+                            break;
+                        },
+                        _ => b"250 OK\r\n",
+                    };
+                    stream.write_all(reply).unwrap();
+                }
+            }
+        });
+
+        SmtpSink { port, messages }
+    }
+}
+
+/// Returns the decoded content of the first part of the message with the content type.
+fn decode_part(message: &str, content_type: &str) -> Option<Vec<u8>> {
+    let start = message.find(&format!("Content-Type: {}", content_type))?;
+    let part = &message[start..];
+    let body_start = part.find("\r\n\r\n")? + 4;
+    let body: String = part[body_start..].lines().take_while(|l| !l.starts_with("--")).collect();
+    base64::decode(body).ok()
+}
+
+/// Returns the value of the header with folded lines joined.
+fn header(message: &str, name: &str) -> String {
+    let headers = message.split("\r\n\r\n").next().unwrap().replace("\r\n ", " ");
+    let prefix = format!("{0}: ", name);
+    headers.lines().find_map(|l| l.strip_prefix(&prefix)).unwrap().to_string()
+}
+
+/// Decodes the RFC 2047 encoded-words in the header value, the space between two of them isn't part of the value.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut after_word = false;
+    for part in value.split(' ') {
+        let word = part.strip_prefix("=?utf-8?B?").and_then(|p| p.strip_suffix("?="));
+        let joined = after_word && word.is_some();
+        if !decoded.is_empty() && !joined {
+            decoded.push(' ');
+        }
+        match word {
+            Some(word) => decoded.push_str(&String::from_utf8(base64::decode(word).unwrap()).unwrap()),
+            None => decoded.push_str(part),
+        }
+        after_word = word.is_some();
+    }
+    decoded
+}
+
+#[test]
+fn sends_new_posts_once() {
+    let server = MockServer::start(&[]);
+    let sink = SmtpSink::start();
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("famly-dl")).unwrap();
+    std::fs::write(dir.path().join("famly-dl").join("config.toml"), format!(r#"
+[profiles.test.digest]
+smtp_host = "127.0.0.1"
+smtp_port = {0}
+security = "none"
+from = "Famly-Nachrichten für Großmama und Großpapa <famly@example.com>"
+to = ["Großmama <grandma@example.com>", "grandpa@example.com"]
+"#, sink.port)).unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    // The fixtures are older than a day, which the first digest covers by default.
+    let output = run(&server, "mock-token", dir.path(), &["digest", "--since", "2024-01-01"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let messages: Vec<_> = sink.messages.lock().unwrap().drain(..).collect();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].recipients, vec!["<grandma@example.com>", "<grandpa@example.com>"]);
+    let message = &messages[0].data;
+    assert!(message.contains("Subject: Famly: 4 new posts\r\n"));
+    assert!(message.contains("\r\nTo: =?utf-8?B?R3Jvw59tYW1h?= <grandma@example.com>, grandpa@example.com\r\n"));
+    let from = header(message, "From");
+    assert!(from.split(' ').all(|word| word.len() <= 75), "{}", from);
+    assert_eq!(decode_words(&from), "Famly-Nachrichten für Großmama und Großpapa <famly@example.com>");
+    let html = String::from_utf8(decode_part(message, "text/html").unwrap()).unwrap();
+    assert!(html.contains("Trip to the zoo"));
+    assert_eq!(html.matches(r#"src="cid:"#).count(), 4, "The photos tagged with the children");
+    assert!(decode_part(message, "image/jpeg").unwrap().starts_with(&[0xff, 0xd8]));
+
+    let output = run(&server, "mock-token", dir.path(), &["digest", "--since", "2024-01-01"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No new posts since the last digest"));
+    assert!(sink.messages.lock().unwrap().is_empty());
+}
@@ -0,0 +1,101 @@
+//! Tests of the Immich export against a stub of the Immich API.
+
+mod common;
+
+use common::{stderr, sync, MockServer, StubServer};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tiny_http::{Header, Response};
+
+const API_KEY: &str = "immich-key";
+
+/// Answers the Immich API requests made by the export.
+fn start_immich() -> StubServer {
+    let mut albums = vec![];
+    let mut assets = 0;
+    StubServer::start(move |request| {
+        if request.header("x-api-key") != Some(API_KEY) {
+            return Response::from_data(vec![]).with_status_code(401);
+        }
+        let response = match (request.method.as_str(), request.url.as_str()) {
+            ("GET", "/api/albums") => json!(albums),
+            ("POST", "/api/albums") => {
+                let name = serde_json::from_str::<Value>(&request.text()).unwrap()["albumName"].clone();
+                albums.push(json!({ "id": format!("album-{}", albums.len() + 1), "albumName": name }));
+                albums.last().unwrap().clone()
+            },
+            ("POST", "/api/assets") => {
+                assets += 1;
+                json!({ "id": format!("asset-{}", assets), "status": "created" })
+            },
+            _ => json!({}),
+        };
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        Response::from_string(response.to_string()).with_header(header)
+    })
+}
+
+/// Removes the recorded requests starting with the prefix and returns them as `METHOD path body`.
+fn take_requests(immich: &StubServer, prefix: &str) -> Vec<String> {
+    immich.take(prefix).iter().map(ToString::to_string).collect()
+}
+
+fn configure(dir: &TempDir, immich: &StubServer, api_key: &str) {
+    let config_dir = dir.path().join("famly-dl");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), format!(
+        "[profiles.test.immich]\nurl = \"{}\"\napi_key = {{ value = \"{}\" }}\n", immich.url, api_key)).unwrap();
+}
+
+#[test]
+fn exports_tagged_photos_once() {
+    let server = MockServer::start(&[]);
+    let immich = start_immich();
+    let dir = TempDir::new().unwrap();
+    configure(&dir, &immich, API_KEY);
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // The zoo photo is tagged with both children, so it's uploaded once and added to both albums.
+    let uploads = take_requests(&immich, "POST /api/assets");
+    assert_eq!(uploads.len(), 8);
+    assert!(uploads.iter().any(|u| u.contains("famly-1c0ffee-image") && u.contains("2024-05-03T09:00:00Z")));
+    let mut albums = take_requests(&immich, "POST /api/albums");
+    albums.sort();
+    assert_eq!(albums, vec![
+        r#"POST /api/albums {"albumName":"Emma 2023/24"}"#,
+        r#"POST /api/albums {"albumName":"Noah 2023/24"}"#,
+    ]);
+
+    take_requests(&immich, "");
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(take_requests(&immich, ""), vec!["GET /api/albums "], "Nothing is exported again");
+}
+
+#[test]
+fn describes_photos_with_the_post_text() {
+    let server = MockServer::start(&[]);
+    let immich = start_immich();
+    let dir = TempDir::new().unwrap();
+    configure(&dir, &immich, API_KEY);
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let descriptions = take_requests(&immich, "PUT /api/assets/");
+    assert_eq!(descriptions.len(), 4, "Only photos of posts have descriptions");
+    assert!(descriptions.iter().any(|d| d.ends_with(r#"{"description":"Trip to the zoo"}"#)));
+}
+
+#[test]
+fn reports_rejected_api_keys() {
+    let server = MockServer::start(&[]);
+    let immich = start_immich();
+    let dir = TempDir::new().unwrap();
+    configure(&dir, &immich, "wrong-key");
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Immich rejected the API key"), "{}", stderr(&output));
+}
@@ -0,0 +1,61 @@
+//! Tests of the notifications about new posts against a local HTTP listener.
+
+mod common;
+
+use common::{stderr, sync, MockServer, StubServer};
+use serde_json::Value;
+use tempfile::TempDir;
+use tiny_http::Response;
+
+#[test]
+fn notifies_about_new_posts_once() {
+    let server = MockServer::start(&[]);
+    // Answers `/broken` with an error.
+    let listener = StubServer::start(|request| {
+        let status = if request.url == "/broken" { 500 } else { 200 };
+        Response::from_string("{}").with_status_code(status)
+    });
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("famly-dl")).unwrap();
+    std::fs::write(dir.path().join("famly-dl").join("config.toml"), format!(r#"
+[[profiles.test.notify]]
+type = "webhook"
+url = "{0}/broken"
+
+[[profiles.test.notify]]
+type = "webhook"
+url = "{0}/hook"
+
+[[profiles.test.notify]]
+type = "ntfy"
+url = "{0}/famly-posts"
+
+[[profiles.test.notify]]
+type = "gotify"
+url = "{0}/gotify"
+token = {{ value = "gotify-token" }}
+"#, listener.url)).unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "A failed notification doesn't fail the sync: {}", stderr(&output));
+    assert!(stderr(&output).contains("Cannot send the notification"), "{}", stderr(&output));
+    let received = listener.take("");
+    assert_eq!(received.iter().map(|r| r.url.as_str()).collect::<Vec<_>>(),
+        vec!["/broken", "/hook", "/famly-posts", "/gotify/message"]);
+
+    let webhook: Value = serde_json::from_str(&received[1].text()).unwrap();
+    assert_eq!(webhook["title"], "4 new Famly posts");
+    assert_eq!(webhook["posts"][0]["title"], "Trip to the zoo");
+    assert_eq!(webhook["posts"][0]["photos"], 1);
+
+    let ntfy = &received[2];
+    assert_eq!(ntfy.header("Title"), Some("4 new Famly posts"));
+    assert!(ntfy.header("Attach").unwrap().starts_with(&server.url));
+    assert!(ntfy.text().lines().next().unwrap().ends_with("Trip to the zoo (1 photo)"), "{}", ntfy.text());
+
+    assert_eq!(received[3].header("X-Gotify-Key"), Some("gotify-token"));
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(listener.take("").is_empty(), "No new posts, no notifications");
+}
@@ -0,0 +1,27 @@
+//! Tests of reading posts from the feed json.
+
+use famly_dl::Post;
+use serde_json::json;
+
+#[test]
+fn keys_comments_without_an_id_by_their_position() {
+    let comment = |id: Option<&str>| {
+        let mut comment = json!({ "createdDate": "2024-05-03T09:00:00Z", "body": "Lovely!", "sender": { "name": "Parent" } });
+        if let Some(id) = id {
+            comment["commentId"] = json!(id);
+        }
+        comment
+    };
+    let json = json!({
+        "feedItemId": "post-1",
+        "createdDate": "2024-05-03T09:00:00Z",
+        "body": "Trip to the zoo",
+        "sender": { "name": "Teacher Anna" },
+        "images": [],
+        "comments": [comment(Some("comment-1")), comment(None)],
+    });
+
+    let post = Post::try_from(&json).unwrap();
+    let ids: Vec<&str> = post.comments.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, ["comment-1", "post-1-1"]);
+}
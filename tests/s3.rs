@@ -0,0 +1,103 @@
+//! Tests of writing the archive to a stub of an S3-compatible object storage.
+
+mod common;
+
+use common::{stderr, sync, MockServer, StubServer, EMMA, NOAH};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tiny_http::Response;
+
+const BUCKET: &str = "famly";
+/// Keys listed per response, so that listing a folder takes several requests.
+const PAGE_SIZE: usize = 2;
+
+/// Keeps the objects in memory, answers with the XML of S3 where the client reads it.
+fn start_s3(objects: Arc<Mutex<BTreeMap<String, Vec<u8>>>>) -> StubServer {
+    StubServer::start(move |request| {
+        if !request.header("Authorization").unwrap_or_default().starts_with("AWS4-HMAC-SHA256 Credential=s3-key/") {
+            return Response::from_data(vec![]).with_status_code(403);
+        }
+        let (path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+        let path = urlencoding::decode(path).unwrap();
+        let mut objects = objects.lock().unwrap();
+        let key = match path.strip_prefix(&format!("/{}", BUCKET)) {
+            Some(key) => key.trim_start_matches('/').to_string(),
+            None => return Response::from_string("<Error><Code>NoSuchBucket</Code></Error>").with_status_code(404),
+        };
+
+        match request.method.as_str() {
+            "PUT" => {
+                let content = match request.header("x-amz-copy-source") {
+                    Some(source) => {
+                        let source = urlencoding::decode(source).unwrap();
+                        objects[source.trim_start_matches(&format!("/{}/", BUCKET))].clone()
+                    },
+                    None => request.body.clone(),
+                };
+                objects.insert(key, content);
+                Response::from_data(vec![])
+            },
+            "HEAD" if objects.contains_key(&key) => Response::from_data(vec![]),
+            "HEAD" => Response::from_data(vec![]).with_status_code(404),
+            "GET" => {
+                let query: BTreeMap<String, String> = query.split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(k, v)| (k.to_string(), urlencoding::decode(v).unwrap().into_owned()))
+                    .collect();
+                assert_eq!(query["list-type"], "2");
+                let start: usize = query.get("continuation-token").map_or(0, |t| t.parse().unwrap());
+                let keys: Vec<&String> = objects.keys().filter(|k| k.starts_with(&query["prefix"])).collect();
+                let mut xml = String::from("<ListBucketResult>");
+                for key in keys.iter().skip(start).take(PAGE_SIZE) {
+                    xml.push_str(&format!("<Contents><Key>{}</Key></Contents>", key.replace('&', "&amp;")));
+                }
+                if start + PAGE_SIZE < keys.len() {
+                    xml.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", start + PAGE_SIZE));
+                }
+                xml.push_str("</ListBucketResult>");
+                Response::from_string(xml)
+            },
+            _ => Response::from_data(vec![]).with_status_code(405),
+        }
+    })
+}
+
+fn configure(dir: &TempDir, s3: &StubServer) {
+    let config_dir = dir.path().join("famly-dl");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), format!(
+        "[profiles.test.storage]\ntype = \"s3\"\nendpoint = \"{}\"\nbucket = \"{}\"\nprefix = \"archive\"\n\
+        access_key_id = \"s3-key\"\nsecret_access_key = {{ value = \"s3-secret\" }}\n", s3.url, BUCKET)).unwrap();
+}
+
+#[test]
+fn writes_the_archive_to_the_bucket() {
+    let server = MockServer::start(&[]);
+    let objects = Arc::new(Mutex::new(BTreeMap::new()));
+    let s3 = start_s3(objects.clone());
+    let dir = TempDir::new().unwrap();
+    configure(&dir, &s3);
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let keys: Vec<String> = objects.lock().unwrap().keys().cloned().collect();
+    assert!(keys.iter().all(|k| k.starts_with("archive/")), "{:?}", keys);
+    assert!(keys.contains(&"archive/index.htm".to_string()), "{:?}", keys);
+    let tagged = |child: &str| keys.iter().filter(|k| k.starts_with(&format!("archive/{}/tagged_photos/", child))).count();
+    assert_eq!(tagged(EMMA), 6);
+    assert_eq!(tagged(NOAH), 3);
+    assert!(s3.take("PUT ").iter().any(|r| r.header("x-amz-copy-source").is_some()), "Tagged photos of posts are copies");
+
+    let lists = s3.take("GET /famly?");
+    assert!(lists.iter().any(|r| r.url.contains("continuation-token=")), "Listings take several pages");
+    let emma_index = String::from_utf8(objects.lock().unwrap()[&format!("archive/{}/index.htm", EMMA)].clone()).unwrap();
+    assert!(emma_index.contains("Trip to the zoo") && emma_index.contains("Garden day"));
+
+    s3.take("");
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!s3.take("HEAD ").is_empty());
+    assert!(s3.take("PUT ").iter().all(|r| !r.url.contains("/tagged_photos/")), "Stored photos are not written again");
+}
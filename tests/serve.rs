@@ -0,0 +1,47 @@
+//! Tests of serving the archive over HTTP.
+
+mod common;
+
+use common::{command, list_files, stderr, sync, MockServer, Running, EMMA};
+use tempfile::TempDir;
+
+#[test]
+fn serves_archive_with_search_and_thumbnails() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let outside = TempDir::new().unwrap();
+    std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("outside")).unwrap();
+
+    let serving = Running::start(&mut command(&server, "mock-token", dir.path(), &["serve", "--address", "127.0.0.1:0"]));
+    let url = serving.wait_for_url();
+    let get = |path: &str| reqwest::blocking::get(format!("{0}{1}", url, path)).unwrap();
+
+    assert!(get("/").text().unwrap().contains(&format!("{0}/index.htm", EMMA)));
+    // Redirected to the folder, so that relative links work.
+    let child_index = get(&format!("/{0}", EMMA));
+    assert_eq!(child_index.url().path(), format!("/{0}/", EMMA));
+    assert!(child_index.text().unwrap().contains("posts/"));
+    assert_eq!(get("/search.js").headers()["Content-Type"], "text/javascript; charset=utf-8");
+    assert!(get(&format!("/{0}/tagged_photos/", EMMA)).text().unwrap().contains(".jpg"));
+    assert_eq!(get("/.famly-dl.lock").status(), 404);
+    assert_eq!(get("/..%5C..%5Csecret.txt").status(), 404);
+    #[cfg(unix)]
+    assert_eq!(get("/outside/secret.txt").status(), 404);
+
+    let found = get("/search?q=PAINTING+two").text().unwrap();
+    assert!(found.contains("1 post found") && found.contains("Painting, part two"), "{}", found);
+    let found = get("/search?q=lovely").text().unwrap();
+    assert!(found.contains("Trip to the zoo"), "Comments are searched too: {}", found);
+
+    let photo = list_files(&dir.path().join(EMMA).join("posts").join("photos")).remove(0);
+    let photo = photo.strip_prefix(dir.path()).unwrap().to_string_lossy().to_string();
+    let thumbnail = get(&format!("/{0}?thumbnail=8", photo));
+    assert_eq!(thumbnail.headers()["Content-Type"], "image/jpeg");
+    let thumbnail = image::load_from_memory(&thumbnail.bytes().unwrap()).unwrap();
+    assert!(thumbnail.width() <= 8 && thumbnail.height() <= 8);
+}
@@ -0,0 +1,107 @@
+//! Tests of the pagination over the fixtures in `tests/fixtures/mock`, without a server.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+
+use famly_dl::client::Result;
+use famly_dl::{DateRange, FeedSource, FixtureSource};
+
+fn fixtures(page_size: usize) -> FixtureSource {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mock");
+    FixtureSource::new(&dir, "http://fixtures", page_size)
+}
+
+#[test]
+fn paginates_posts_sharing_a_date() {
+    // Three posts share a date, a page must be able to hold at least two of them.
+    for page_size in 2..=4 {
+        let source = fixtures(page_size);
+        let children = source.child_infos().unwrap();
+        let child_ids: Vec<&String> = children.iter().map(|c| &c.id).collect();
+
+        let mut ids: Vec<String> = source.posts(&child_ids, &DateRange::default())
+            .flat_map(|page| page.unwrap())
+            .map(|p| p.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["post-1", "post-2", "post-3", "post-5"], "page size {}", page_size);
+    }
+}
+
+/// Answers the n-th request for tagged photos with the photos returned by `page(n)` as `(id, date)`,
+/// remembering the requested cursors.
+struct ScriptedSource<F: Fn(usize) -> Vec<(&'static str, &'static str)>> {
+    page: F,
+    requests: RefCell<Vec<Option<String>>>,
+}
+
+impl<F: Fn(usize) -> Vec<(&'static str, &'static str)>> ScriptedSource<F> {
+    fn new(page: F) -> Self {
+        ScriptedSource { page, requests: RefCell::new(vec![]) }
+    }
+
+    fn tagged_photo_ids(&self) -> Vec<Result<Vec<String>>> {
+        self.tagged_photos("child", &DateRange::default())
+            .map(|page| page.map(|photos| photos.into_iter().map(|p| p.id).collect()))
+            .collect()
+    }
+}
+
+impl<F: Fn(usize) -> Vec<(&'static str, &'static str)>> FeedSource for ScriptedSource<F> {
+    fn child_infos_json(&self) -> Result<String> {
+        Ok(r#"{"children": []}"#.to_string())
+    }
+
+    fn feed_page(&self, _older_than: &Option<String>) -> Result<String> {
+        Ok(r#"{"feedItems": []}"#.to_string())
+    }
+
+    fn tagged_photos_page(&self, _child_id: &str, older_than: &Option<String>) -> Result<String> {
+        let mut requests = self.requests.borrow_mut();
+        let photos: Vec<_> = (self.page)(requests.len()).into_iter()
+            .map(|(id, date)| serde_json::json!({
+                "imageId": id, "createdAt": date, "prefix": "http://fixtures", "key": id,
+                "width": 1, "height": 1, "tags": [{ "childId": "child" }],
+            }))
+            .collect();
+        requests.push(older_than.clone());
+        Ok(serde_json::Value::Array(photos).to_string())
+    }
+
+    fn download_image(&self, _url: &str, _writer: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn overlaps_pages_ending_on_a_shared_date() {
+    let source = ScriptedSource::new(|request| match request {
+        0 => vec![("a", "2024-05-02T10:00:05Z"), ("b", "2024-05-02T10:00:00Z")],
+        // The server returns `b` again along with `c` of the same date, which a strict cursor would skip.
+        1 => vec![("b", "2024-05-02T10:00:00Z"), ("c", "2024-05-02T10:00:00Z")],
+        _ => vec![],
+    });
+
+    let ids: Vec<String> = source.tagged_photo_ids().into_iter().flat_map(|page| page.unwrap()).collect();
+    assert_eq!(ids, ["a", "b", "c"]);
+    assert_eq!(*source.requests.borrow(), [
+        None,
+        Some("2024-05-02T10:00:01Z".to_string()),
+        // Never later than the previous cursor.
+        Some("2024-05-02T10:00:00.999Z".to_string()),
+    ]);
+}
+
+#[test]
+fn stops_on_a_stuck_cursor() {
+    // The server ignores the cursor and returns the same page over and over.
+    let source = ScriptedSource::new(|_| vec![("a", "2024-05-02T10:00:00Z"), ("b", "2024-05-02T10:00:00Z")]);
+
+    let pages = source.tagged_photo_ids();
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0].as_ref().unwrap(), &["a", "b"]);
+    assert!(pages[1].as_ref().unwrap().is_empty());
+    let error = pages[2].as_ref().unwrap_err().to_string();
+    assert!(error.contains("doesn't advance"), "{}", error);
+}
@@ -0,0 +1,209 @@
+//! End-to-end tests running `famly-dl` against the mock server with the fixtures in `tests/fixtures/mock`.
+
+mod common;
+
+use common::{list_files, run, stderr, sync, MockServer, EMMA, NOAH};
+use tempfile::TempDir;
+
+#[test]
+fn syncs_all_children() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let emma = dir.path().join(EMMA);
+    let noah = dir.path().join(NOAH);
+    assert!(dir.path().join("index.htm").is_file());
+    assert!(emma.join("index.htm").is_file());
+    assert_eq!(list_files(&emma.join("posts")).len(), 4, "3 posts and the photos folder");
+    assert_eq!(list_files(&noah.join("posts")).len(), 3, "2 posts and the photos folder");
+    // 3 feed photos and 3 tagged ones, two of which share a timestamp across pages.
+    assert_eq!(list_files(&emma.join("tagged_photos")).len(), 6);
+    assert_eq!(list_files(&noah.join("tagged_photos")).len(), 3);
+}
+
+#[test]
+fn keeps_older_posts_in_the_indexes_when_syncing_a_date_range() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = run(&server, "mock-token", dir.path(), &["--since", "2024-05-03"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let emma_index = std::fs::read_to_string(dir.path().join(EMMA).join("index.htm")).unwrap();
+    assert!(emma_index.contains("Trip to the zoo"));
+    assert!(emma_index.contains("Garden day"), "{}", emma_index);
+    let noah_index = std::fs::read_to_string(dir.path().join(NOAH).join("index.htm")).unwrap();
+    assert!(noah_index.contains("Painting part two"), "{}", noah_index);
+    let family_index = std::fs::read_to_string(dir.path().join("index.htm")).unwrap();
+    assert!(family_index.contains("Garden day"), "{}", family_index);
+}
+
+#[test]
+fn prunes_recorded_runs_covered_by_a_more_recent_one() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+    let feed_runs = || list_files(&dir.path().join("raw").join("feed"));
+
+    for _ in 0..2 {
+        let output = sync(&server, "mock-token", dir.path());
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    assert_eq!(feed_runs().len(), 1, "{:?}", feed_runs());
+    assert_eq!(list_files(&dir.path().join(EMMA).join("raw").join("tagged_photos")).len(), 1);
+
+    let output = run(&server, "mock-token", dir.path(), &["--since", "2024-05-03"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(feed_runs().len(), 2, "The older run has posts the limited one doesn't");
+}
+
+#[test]
+fn retries_rate_limited_requests() {
+    let server = MockServer::start(&["--fail", "feed:2:429", "--fail", "tagged:1:429"]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(list_files(&dir.path().join(EMMA).join("tagged_photos")).len(), 6);
+}
+
+#[test]
+fn reports_server_errors() {
+    let server = MockServer::start(&["--fail", "feed:2:500"]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("try again later"), "{}", stderr(&output));
+}
+
+#[test]
+fn reports_rejected_tokens() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "wrong-token", dir.path());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("rejected the access token"), "{}", stderr(&output));
+}
+
+#[test]
+fn reports_expired_images() {
+    let server = MockServer::start(&["--expired-images"]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("run again to get fresh links"), "{}", stderr(&output));
+    assert!(list_files(&dir.path().join(EMMA).join("posts").join("photos")).is_empty());
+}
+
+#[test]
+fn writes_takeout_sidecars() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("famly-dl")).unwrap();
+    std::fs::write(dir.path().join("famly-dl").join("config.toml"), "[profiles.test.include]\ntakeout_sidecars = true\n").unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let tagged_photos = dir.path().join(NOAH).join("tagged_photos");
+    assert_eq!(list_files(&tagged_photos).len(), 6, "3 photos and their sidecars");
+    let sidecar: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(tagged_photos.join("2024-05-03_09-00-00_1c0f.jpg.json")).unwrap()).unwrap();
+    assert_eq!(sidecar["description"], "Trip to the zoo");
+    assert_eq!(sidecar["photoTakenTime"]["timestamp"], "1714726800");
+    assert_eq!(sidecar["people"], serde_json::json!([{ "name": "Emma" }, { "name": "Noah" }]));
+}
+
+#[test]
+fn writes_search_indexes() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let read_index = |path: std::path::PathBuf| -> serde_json::Value {
+        let script = std::fs::read_to_string(path).unwrap();
+        let json = script.strip_prefix("window.FAMLY_SEARCH_INDEX = ").unwrap().trim_end().strip_suffix(';').unwrap();
+        serde_json::from_str(json).unwrap()
+    };
+    let noah = read_index(dir.path().join(NOAH).join("search.js"));
+    assert_eq!(noah.as_array().unwrap().len(), 2);
+    assert_eq!(noah[0]["author"], "Teacher Anna");
+    assert_eq!(noah[0]["comments"], serde_json::json!(["Parent | Emma: Lovely!"]));
+    assert!(noah[0]["url"].as_str().unwrap().starts_with("posts/"));
+
+    let family = read_index(dir.path().join("search.js"));
+    assert_eq!(family.as_array().unwrap().len(), 4);
+    assert!(family[0]["url"].as_str().unwrap().starts_with(EMMA));
+    assert!(std::fs::read_to_string(dir.path().join(EMMA).join("index.htm")).unwrap().contains(r#"<script src="search.js">"#));
+}
+
+#[test]
+fn exports_to_sqlite() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    for _ in 0..2 {
+        let output = run(&server, "mock-token", dir.path(), &["--sqlite"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    let db = rusqlite::Connection::open(dir.path().join("famly.sqlite")).unwrap();
+    let count = |sql: &str| -> i64 { db.query_row(sql, [], |row| row.get(0)).unwrap() };
+    assert_eq!(count("SELECT count(*) FROM children"), 2);
+    assert_eq!(count("SELECT count(*) FROM posts"), 4, "Updated, not duplicated by the second run");
+    let comment: String = db.query_row("SELECT id FROM comments WHERE post_id = 'post-1'", [], |row| row.get(0)).unwrap();
+    assert_eq!(comment, "comment-1");
+    assert_eq!(count("SELECT count(*) FROM attachments"), 1);
+    assert_eq!(count("SELECT count(*) FROM photos WHERE post_id IS NULL"), 4, "Tagged photos not shared in posts");
+    assert_eq!(count(&format!("SELECT count(*) FROM photo_tags WHERE child_id = '{0}'", NOAH)), 3);
+
+    let found: String = db.query_row(
+        "SELECT posts.date FROM posts_fts JOIN posts ON posts.rowid = posts_fts.rowid WHERE posts_fts MATCH 'zoo'",
+        [], |row| row.get(0)).unwrap();
+    assert_eq!(found, "2024-05-03T09:00:00Z");
+}
+
+#[test]
+fn exports_jsonl_and_csv() {
+    let server = MockServer::start(&[]);
+    let dir = TempDir::new().unwrap();
+
+    let output = run(&server, "mock-token", dir.path(), &["--jsonl", "--csv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = run(&server, "mock-token", dir.path(), &["--jsonl", "--csv", "--since", "2024-05-03"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let export = dir.path().join("export");
+    let jsonl = std::fs::read_to_string(export.join("posts.jsonl")).unwrap();
+    let posts: Vec<serde_json::Value> = jsonl.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(posts.len(), 4, "A date range doesn't limit the export");
+    assert_eq!(posts[0]["id"], "post-1");
+    assert_eq!(posts[0]["date"], "2024-05-03T09:00:00Z");
+    assert_eq!(posts[0]["photos"][0]["tags"], serde_json::json!([EMMA, NOAH]));
+    assert_eq!(posts[0]["comments"][0]["id"], "comment-1");
+    assert_eq!(posts[0]["comments"][0]["text"], "Lovely!");
+    assert_eq!(posts[0]["attachments"][0]["name"], "Zoo packing list.pdf");
+
+    let read_csv = |name: &str| -> Vec<Vec<String>> {
+        csv::Reader::from_path(export.join(name)).unwrap().records()
+            .map(|r| r.unwrap().iter().map(String::from).collect())
+            .collect()
+    };
+    let csv_posts = read_csv("posts.csv");
+    assert_eq!(csv_posts.len(), 4);
+    assert_eq!(csv_posts[0][..4], ["post-1", "2024-05-03T09:00:00Z", "Teacher Anna", "Trip to the zoo"]);
+    let photos = read_csv("photos.csv");
+    assert_eq!(photos.len(), 8, "4 photos of posts and 4 tagged photos");
+    assert_eq!(photos[0][4], format!("{0};{1}", EMMA, NOAH));
+    assert_eq!(photos.iter().filter(|p| p[1].is_empty()).count(), 4);
+    assert_eq!(read_csv("comments.csv"), vec![vec!["comment-1", "post-1", "2024-05-03T09:00:00Z", "Parent | Emma", "Lovely!"]]);
+}
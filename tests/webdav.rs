@@ -0,0 +1,110 @@
+//! Tests of the upload to a stub of a WebDAV server.
+
+mod common;
+
+use common::{stderr, sync, MockServer, StubServer, EMMA, NOAH};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tiny_http::Response;
+
+/// Exists on the server before the first upload, the archive goes to the `Famly` folder in it.
+const USER_FOLDER: &str = "/remote.php/dav/files/mom";
+
+/// Folders and files of the WebDAV server, by their decoded path without a trailing slash.
+#[derive(Default)]
+struct Files {
+    folders: BTreeSet<String>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Answers like a WebDAV server: files and folders can only be created in existing folders.
+fn start_webdav(files: Arc<Mutex<Files>>) -> StubServer {
+    let mut folder = String::new();
+    for part in USER_FOLDER.split('/').skip(1) {
+        folder = format!("{}/{}", folder, part);
+        files.lock().unwrap().folders.insert(folder.clone());
+    }
+
+    StubServer::start(move |request| {
+        if request.header("Authorization") != Some(&format!("Basic {}", base64::encode("mom:secret"))) {
+            return Response::from_data(vec![]).with_status_code(401);
+        }
+        let path = urlencoding::decode(&request.url).unwrap().trim_end_matches('/').to_string();
+        let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or_default();
+        let mut files = files.lock().unwrap();
+        let status = match request.method.as_str() {
+            _ if !files.folders.contains(parent) => 409,
+            "MKCOL" if files.folders.contains(&path) || files.files.contains_key(&path) => 405,
+            "MKCOL" => {
+                files.folders.insert(path);
+                201
+            },
+            "PUT" => {
+                files.files.insert(path, request.body.clone());
+                201
+            },
+            _ => 405,
+        };
+        Response::from_data(vec![]).with_status_code(status)
+    })
+}
+
+fn configure(dir: &TempDir, webdav: &StubServer) {
+    let config_dir = dir.path().join("famly-dl");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), format!(
+        "[profiles.test.upload]\ntype = \"webdav\"\nurl = \"{}{}/Famly\"\nusername = \"mom\"\n\
+        password = {{ value = \"secret\" }}\n", webdav.url, USER_FOLDER)).unwrap();
+}
+
+/// Removes the recorded uploads and returns their paths in the archive.
+fn take_uploads(webdav: &StubServer) -> Vec<String> {
+    let root = format!("PUT {}/Famly/", USER_FOLDER);
+    let mut uploads: Vec<String> = webdav.take("PUT ").iter()
+        .map(|r| urlencoding::decode(&format!("PUT {}", r.url)).unwrap().trim_start_matches(&root).to_string())
+        .collect();
+    uploads.sort();
+    uploads
+}
+
+#[test]
+fn uploads_new_and_changed_files() {
+    let server = MockServer::start(&[]);
+    let files = Arc::new(Mutex::new(Files::default()));
+    let webdav = start_webdav(files.clone());
+    let dir = TempDir::new().unwrap();
+    configure(&dir, &webdav);
+
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let uploads = take_uploads(&webdav);
+    assert!(uploads.contains(&"index.htm".to_string()), "{:?}", uploads);
+    assert!(uploads.contains(&"search.js".to_string()), "The family index needs its search: {:?}", uploads);
+    assert!(uploads.contains(&format!("{}/index.htm", EMMA)), "{:?}", uploads);
+    assert_eq!(uploads.iter().filter(|u| u.starts_with(&format!("{}/tagged_photos/", NOAH))).count(), 3);
+    assert!(!uploads.iter().any(|u| u.contains("/raw/")), "Recorded API responses stay local");
+    let folders = files.lock().unwrap().folders.clone();
+    assert!(folders.contains(&format!("{}/Famly/{}/tagged_photos", USER_FOLDER, EMMA)), "{:?}", folders);
+    let state = std::fs::read_to_string(dir.path().join(".upload-state.json")).unwrap();
+    assert!(state.contains(&format!("{}/index.htm", EMMA)), "{}", state);
+
+    // The posts and indexes are written again by the sync, but their content didn't change.
+    webdav.take("");
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(take_uploads(&webdav), Vec::<String>::new());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("0 files uploaded"));
+
+    let photos = common::list_files(&dir.path().join(NOAH).join("tagged_photos"));
+    let mut photo = std::fs::read(&photos[0]).unwrap();
+    photo.extend(b"edited");
+    // Replaced rather than edited, as the photo is a hard link of the one in the post.
+    std::fs::remove_file(&photos[0]).unwrap();
+    std::fs::write(&photos[0], &photo).unwrap();
+    let output = sync(&server, "mock-token", dir.path());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let name = photos[0].file_name().unwrap().to_str().unwrap();
+    assert_eq!(take_uploads(&webdav), vec![format!("{}/tagged_photos/{}", NOAH, name)]);
+}